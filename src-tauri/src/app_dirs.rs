@@ -0,0 +1,237 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::config_layers;
+
+const APP_DIR_NAME: &str = "alpha-centauri";
+const USER_CONFIG_STEM: &str = "config";
+// next to the executable, its presence switches the app to portable mode
+const PORTABLE_CONFIG_STEM: &str = "alphacentauri.config";
+const PORTABLE_STATE_DIR_NAME: &str = "alphacentauri.state";
+const PORTABLE_THEMES_DIR_NAME: &str = "alphacentauri.themes";
+const THEMES_DIR_NAME: &str = "themes";
+const LEGACY_CONFIG_STEM: &str = ".alphacentauri.config";
+
+// The directories the app reads from and writes to, resolved once from the environment
+#[derive(Debug, Clone, Default)]
+pub struct BaseDirs {
+    pub home: Option<PathBuf>,
+    pub config_home: Option<PathBuf>,
+    pub state_home: Option<PathBuf>,
+    pub exe_dir: Option<PathBuf>,
+}
+
+// the XDG spec says relative paths in these variables are invalid and must be ignored
+fn absolute_env_dir(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
+
+impl BaseDirs {
+    // $XDG_CONFIG_HOME and $XDG_STATE_HOME, falling back to ~/.config and ~/.local/state,
+    // or %APPDATA% and %LOCALAPPDATA% on Windows
+    pub fn from_env() -> BaseDirs {
+        let home = dir::home_dir();
+
+        #[cfg(target_os = "windows")]
+        let (default_config_home, default_state_home) = (
+            absolute_env_dir("APPDATA"),
+            absolute_env_dir("LOCALAPPDATA"),
+        );
+        #[cfg(not(target_os = "windows"))]
+        let (default_config_home, default_state_home) = (
+            home.as_ref().map(|home| home.join(".config")),
+            home.as_ref().map(|home| home.join(".local").join("state")),
+        );
+
+        BaseDirs {
+            config_home: absolute_env_dir("XDG_CONFIG_HOME").or(default_config_home),
+            state_home: absolute_env_dir("XDG_STATE_HOME").or(default_state_home),
+            exe_dir: std::env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(Path::to_path_buf)),
+            home,
+        }
+    }
+
+    fn xdg_config_dir(&self) -> Option<PathBuf> {
+        self.config_home.as_ref().map(|dir| dir.join(APP_DIR_NAME))
+    }
+
+    // The user's config file, the first one found of:
+    //   1. alphacentauri.config.{json,toml,yaml,yml} next to the executable (portable mode)
+    //   2. $XDG_CONFIG_HOME/alpha-centauri/config.{json,toml,yaml,yml}
+    //   3. ~/.alphacentauri.config.{json,toml,yaml,yml}, where older versions kept it
+    // When there is none, a new file goes in the XDG location. --config overrides all of these
+    pub fn user_config_file(&self) -> Result<PathBuf, String> {
+        let candidates = [
+            (self.exe_dir.clone(), PORTABLE_CONFIG_STEM),
+            (self.xdg_config_dir(), USER_CONFIG_STEM),
+            (self.home.clone(), LEGACY_CONFIG_STEM),
+        ];
+        for (dir, stem) in candidates {
+            if let Some(path) = dir.and_then(|dir| config_layers::find_config_file(&dir, stem)) {
+                return Ok(path);
+            }
+        }
+
+        let dir = self.xdg_config_dir().ok_or_else(|| {
+            String::from(
+                "Neither the home directory nor XDG_CONFIG_HOME could be found, use --config to choose a configuration file.",
+            )
+        })?;
+        fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.to_string_lossy(), e))?;
+        Ok(dir.join(format!("{}.json", USER_CONFIG_STEM)))
+    }
+
+    fn is_portable(&self) -> bool {
+        self.exe_dir
+            .as_ref()
+            .and_then(|dir| config_layers::find_config_file(dir, PORTABLE_CONFIG_STEM))
+            .is_some()
+    }
+
+    // Whether the file is in a directory the app keeps for itself, so files of its own can be
+    // written next to it. Older versions kept the config in the home directory and --config
+    // can point anywhere
+    pub fn is_app_config_file(&self, file_loc: &Path) -> bool {
+        let app_config_dir = if self.is_portable() {
+            self.exe_dir.clone()
+        } else {
+            self.xdg_config_dir()
+        };
+        app_config_dir.is_some_and(|dir| file_loc.parent() == Some(dir.as_path()))
+    }
+
+    // Where window layouts, history and other state that isn't configuration is kept, next
+    // to the executable in portable mode so nothing is written elsewhere on the machine
+    pub fn state_dir(&self) -> Option<PathBuf> {
+        let dir = if self.is_portable() {
+            self.exe_dir.as_ref()?.join(PORTABLE_STATE_DIR_NAME)
+        } else {
+            self.state_home.as_ref()?.join(APP_DIR_NAME)
+        };
+        fs::create_dir_all(&dir).ok()?;
+        Some(dir)
+    }
+
+    // Where color themes are kept and imported to, it is created when a theme is imported
+    pub fn themes_dir(&self) -> Option<PathBuf> {
+        if self.is_portable() {
+            Some(self.exe_dir.as_ref()?.join(PORTABLE_THEMES_DIR_NAME))
+        } else {
+            Some(self.xdg_config_dir()?.join(THEMES_DIR_NAME))
+        }
+    }
+}
+
+// Moves a state file from where older versions kept it, unless it has already been moved
+pub fn adopt_legacy_state_file(legacy: &Path, current: &Path) {
+    if current.exists() || !legacy.is_file() {
+        return;
+    }
+    if fs::rename(legacy, current).is_err() {
+        // rename fails across file systems
+        if fs::copy(legacy, current).is_ok() {
+            let _ = fs::remove_file(legacy);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn base_dirs(root: &Path) -> BaseDirs {
+        BaseDirs {
+            home: Some(root.join("home")),
+            config_home: Some(root.join("config")),
+            state_home: Some(root.join("state")),
+            exe_dir: Some(root.join("bin")),
+        }
+    }
+
+    #[test]
+    fn user_config_file_follows_lookup_order() {
+        let dir = TempDir::new("app_dirs").unwrap();
+        let dirs = base_dirs(dir.path());
+        for sub_dir in ["home", "config/alpha-centauri", "bin"] {
+            fs::create_dir_all(dir.path().join(sub_dir)).unwrap();
+        }
+
+        let legacy = dir.path().join("home/.alphacentauri.config.json");
+        fs::write(&legacy, "{}").unwrap();
+        assert_eq!(dirs.user_config_file().unwrap(), legacy);
+
+        let xdg = dir.path().join("config/alpha-centauri/config.toml");
+        fs::write(&xdg, "").unwrap();
+        assert_eq!(dirs.user_config_file().unwrap(), xdg);
+
+        let portable = dir.path().join("bin/alphacentauri.config.yaml");
+        fs::write(&portable, "").unwrap();
+        assert_eq!(dirs.user_config_file().unwrap(), portable);
+
+        let _ = dir.close();
+    }
+
+    #[test]
+    fn user_config_file_defaults_to_xdg_location() {
+        let dir = TempDir::new("app_dirs").unwrap();
+
+        let actual = base_dirs(dir.path()).user_config_file().unwrap();
+
+        assert_eq!(actual, dir.path().join("config/alpha-centauri/config.json"));
+        assert!(actual.parent().unwrap().is_dir());
+        assert!(BaseDirs::default().user_config_file().is_err());
+
+        let _ = dir.close();
+    }
+
+    #[test]
+    fn is_app_config_file_excludes_legacy_location() {
+        let dir = TempDir::new("app_dirs").unwrap();
+        let dirs = base_dirs(dir.path());
+
+        assert!(dirs.is_app_config_file(&dir.path().join("config/alpha-centauri/config.json")));
+        assert!(!dirs.is_app_config_file(&dir.path().join("home/.alphacentauri.config.json")));
+        assert!(!dirs.is_app_config_file(&dir.path().join("bin/alphacentauri.config.json")));
+
+        fs::create_dir_all(dir.path().join("bin")).unwrap();
+        fs::write(dir.path().join("bin/alphacentauri.config.json"), "{}").unwrap();
+        assert!(dirs.is_app_config_file(&dir.path().join("bin/alphacentauri.config.json")));
+
+        let _ = dir.close();
+    }
+
+    #[test]
+    fn state_and_themes_dirs_are_next_to_executable_in_portable_mode() {
+        let dir = TempDir::new("app_dirs").unwrap();
+        let dirs = base_dirs(dir.path());
+
+        assert_eq!(
+            dirs.state_dir().unwrap(),
+            dir.path().join("state/alpha-centauri")
+        );
+        assert_eq!(
+            dirs.themes_dir().unwrap(),
+            dir.path().join("config/alpha-centauri/themes")
+        );
+
+        fs::create_dir_all(dir.path().join("bin")).unwrap();
+        fs::write(dir.path().join("bin/alphacentauri.config.json"), "{}").unwrap();
+        assert_eq!(
+            dirs.state_dir().unwrap(),
+            dir.path().join("bin/alphacentauri.state")
+        );
+        assert_eq!(
+            dirs.themes_dir().unwrap(),
+            dir.path().join("bin/alphacentauri.themes")
+        );
+
+        let _ = dir.close();
+    }
+}
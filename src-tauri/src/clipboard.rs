@@ -0,0 +1,78 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+// OSC 52: `ESC ] 52 ; <selection> ; <base64 data | ?> ST`
+pub const CLIPBOARD_OSC_CODE: &str = "52";
+const QUERY: &str = "?";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClipboardRequest {
+    Write { selection: String, text: String },
+    Read { selection: String },
+}
+
+impl ClipboardRequest {
+    pub fn access(&self) -> &'static str {
+        match self {
+            ClipboardRequest::Write { .. } => "write",
+            ClipboardRequest::Read { .. } => "read",
+        }
+    }
+}
+
+pub fn parse_clipboard_request(data: &str) -> Option<ClipboardRequest> {
+    let (selection, payload) = data.split_once(';')?;
+    // an empty selection means the default, which xterm treats as "s 0"
+    let selection = if selection.is_empty() {
+        String::from("s0")
+    } else {
+        selection.to_string()
+    };
+
+    if payload == QUERY {
+        return Some(ClipboardRequest::Read { selection });
+    }
+    let bytes = STANDARD.decode(payload.trim()).ok()?;
+    let text = String::from_utf8(bytes).ok()?;
+    Some(ClipboardRequest::Write { selection, text })
+}
+
+pub fn encode_clipboard_response(selection: &str, text: &str) -> String {
+    format!("\x1b]52;{};{}\x07", selection, STANDARD.encode(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_clipboard_request_decodes_write() {
+        let actual = parse_clipboard_request("c;aGVsbG8gd29ybGQ=");
+
+        assert_eq!(
+            actual,
+            Some(ClipboardRequest::Write {
+                selection: "c".to_string(),
+                text: "hello world".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_clipboard_request_detects_query() {
+        assert_eq!(
+            parse_clipboard_request(";?"),
+            Some(ClipboardRequest::Read {
+                selection: "s0".to_string(),
+            })
+        );
+        assert_eq!(parse_clipboard_request("c;not base64!"), None);
+    }
+
+    #[test]
+    fn encode_clipboard_response_wraps_base64() {
+        assert_eq!(
+            encode_clipboard_response("c", "hello world"),
+            "\x1b]52;c;aGVsbG8gd29ybGQ=\x07"
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::keymap::{KeyBindingConfig, KeyBindings};
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum CommandCategory {
+    Edit,
+    Terminal,
+    Window,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandArg {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub required: bool,
+    // backslash escapes such as \x1b are decoded when the keymaps are loaded
+    pub escapes: bool,
+}
+
+// A command the UI can run from a key binding or the command palette
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandInfo {
+    // "<category>:<name>", the key used in the keymaps config
+    pub id: &'static str,
+    pub description: &'static str,
+    pub category: CommandCategory,
+    pub default_bindings: &'static [&'static str],
+    pub args: &'static [CommandArg],
+}
+
+// Every command, new commands are registered here. Their default bindings become the default
+// keymaps
+pub const COMMANDS: &[CommandInfo] = &[
+    CommandInfo {
+        id: "edit:copy",
+        description: "Copy the selected text to the clipboard",
+        category: CommandCategory::Edit,
+        default_bindings: &["ctrl+shift+c"],
+        args: &[],
+    },
+    CommandInfo {
+        id: "edit:paste",
+        description: "Paste text from the clipboard",
+        category: CommandCategory::Edit,
+        default_bindings: &["ctrl+shift+v"],
+        args: &[],
+    },
+    CommandInfo {
+        id: "edit:select_all",
+        description: "Send ctrl+a to the shell",
+        category: CommandCategory::Edit,
+        default_bindings: &["ctrl+shift+a"],
+        args: &[],
+    },
+    CommandInfo {
+        id: "edit:interrupt",
+        description: "Interrupt the running program (ctrl+c)",
+        category: CommandCategory::Edit,
+        default_bindings: &["ctrl+c"],
+        args: &[],
+    },
+    CommandInfo {
+        id: "terminal:send_text",
+        description: "Write text to the focused shell, e.g. \\x17 to delete the previous word",
+        category: CommandCategory::Terminal,
+        default_bindings: &[],
+        args: &[CommandArg {
+            name: "text",
+            description: "The text to send, backslash escapes such as \\n and \\x1b are decoded",
+            required: true,
+            escapes: true,
+        }],
+    },
+    CommandInfo {
+        id: "window:new_tab",
+        description: "Open a new tab",
+        category: CommandCategory::Window,
+        default_bindings: &["ctrl+shift+t"],
+        args: &[],
+    },
+    CommandInfo {
+        id: "window:next_tab",
+        description: "Switch to the next tab",
+        category: CommandCategory::Window,
+        default_bindings: &["ctrl+shift+ArrowRight"],
+        args: &[],
+    },
+    CommandInfo {
+        id: "window:prev_tab",
+        description: "Switch to the previous tab",
+        category: CommandCategory::Window,
+        default_bindings: &["ctrl+shift+ArrowLeft"],
+        args: &[],
+    },
+    CommandInfo {
+        id: "window:split_right",
+        description: "Split the current pane, opening a new shell on the right",
+        category: CommandCategory::Window,
+        default_bindings: &["ctrl+shift+d"],
+        args: &[],
+    },
+    CommandInfo {
+        id: "window:split_down",
+        description: "Split the current pane, opening a new shell below",
+        category: CommandCategory::Window,
+        default_bindings: &["ctrl+shift+e"],
+        args: &[],
+    },
+];
+
+pub fn default_keymaps() -> HashMap<String, KeyBindings> {
+    COMMANDS
+        .iter()
+        .filter(|command| !command.default_bindings.is_empty())
+        .map(|command| {
+            let bindings = match command.default_bindings {
+                [combo] => KeyBindings::from(*combo),
+                combos => {
+                    KeyBindings::Many(combos.iter().map(|c| KeyBindingConfig::from(*c)).collect())
+                }
+            };
+            (command.id.to_string(), bindings)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap;
+
+    #[test]
+    fn default_bindings_are_valid() {
+        let actual = keymap::resolve_keymaps(&default_keymaps(), &default_keymaps(), COMMANDS);
+
+        assert_eq!(actual.problems, Vec::<String>::new());
+        assert_eq!(actual.bindings.len(), default_keymaps().len());
+    }
+
+    #[test]
+    fn command_ids_are_unique() {
+        let mut ids: Vec<&str> = COMMANDS.iter().map(|command| command.id).collect();
+        ids.sort();
+        ids.dedup();
+
+        assert_eq!(ids.len(), COMMANDS.len());
+    }
+}
@@ -0,0 +1,502 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+// stops a badly broken file from producing an endless list of problems
+const MAX_DIAGNOSTICS: usize = 50;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigDiagnostic {
+    // the file the problem is in, when settings come from more than one place
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    // dotted path to the setting such as shell.args[0], empty for the file as a whole
+    pub path: String,
+    // 1-based position of the offending value in the file
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}: ", file)?;
+        }
+        if self.path.is_empty() {
+            write!(
+                f,
+                "line {}, column {}: {}",
+                self.line, self.column, self.message
+            )
+        } else {
+            write!(
+                f,
+                "line {}, column {}: {} - {}",
+                self.line, self.column, self.path, self.message
+            )
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+pub fn path_to_string(segments: &[PathSegment]) -> String {
+    let mut path = String::new();
+    for segment in segments {
+        match segment {
+            PathSegment::Key(key) => {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(key);
+            }
+            PathSegment::Index(index) => path.push_str(&format!("[{}]", index)),
+        }
+    }
+    path
+}
+
+fn skip_whitespace(chars: &[char], mut pos: usize) -> usize {
+    while pos < chars.len() && chars[pos].is_whitespace() {
+        pos += 1;
+    }
+    pos
+}
+
+// Reads the string starting at pos, returning its content and the index just past it.
+// Escapes are reduced to the escaped character, which is enough to compare keys
+fn read_string(chars: &[char], pos: usize) -> (String, usize) {
+    let mut content = String::new();
+    let mut i = pos + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '"' => return (content, i + 1),
+            '\\' => {
+                if let Some(c) = chars.get(i + 1) {
+                    content.push(*c);
+                }
+                i += 2;
+            }
+            c => {
+                content.push(c);
+                i += 1;
+            }
+        }
+    }
+    (content, i)
+}
+
+// index just past the value starting at pos
+fn skip_value(chars: &[char], pos: usize) -> usize {
+    match chars.get(pos) {
+        Some('"') => read_string(chars, pos).1,
+        Some('{') | Some('[') => {
+            let mut depth = 0;
+            let mut i = pos;
+            while i < chars.len() {
+                match chars[i] {
+                    '"' => {
+                        i = read_string(chars, i).1;
+                        continue;
+                    }
+                    '{' | '[' => depth += 1,
+                    '}' | ']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return i + 1;
+                        }
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+            i
+        }
+        _ => {
+            let mut i = pos;
+            while i < chars.len()
+                && !matches!(chars[i], ',' | '}' | ']')
+                && !chars[i].is_whitespace()
+            {
+                i += 1;
+            }
+            i
+        }
+    }
+}
+
+// index of the value for the segment inside the object or array starting at pos
+fn find_child(chars: &[char], pos: usize, segment: &PathSegment) -> Option<usize> {
+    match (chars.get(pos), segment) {
+        (Some('{'), PathSegment::Key(key)) => {
+            let mut i = pos + 1;
+            loop {
+                i = skip_whitespace(chars, i);
+                if chars.get(i) != Some(&'"') {
+                    return None;
+                }
+                let (name, after_name) = read_string(chars, i);
+                i = skip_whitespace(chars, after_name);
+                if chars.get(i) != Some(&':') {
+                    return None;
+                }
+                let value = skip_whitespace(chars, i + 1);
+                if name == *key {
+                    return Some(value);
+                }
+                i = skip_whitespace(chars, skip_value(chars, value));
+                if chars.get(i) != Some(&',') {
+                    return None;
+                }
+                i += 1;
+            }
+        }
+        (Some('['), PathSegment::Index(index)) => {
+            let mut i = skip_whitespace(chars, pos + 1);
+            for _ in 0..*index {
+                i = skip_whitespace(chars, skip_value(chars, i));
+                if chars.get(i) != Some(&',') {
+                    return None;
+                }
+                i = skip_whitespace(chars, i + 1);
+            }
+            match chars.get(i) {
+                Some(']') | None => None,
+                Some(_) => Some(i),
+            }
+        }
+        _ => None,
+    }
+}
+
+// Line and column of the value at the path, or of its closest existing parent
+pub fn locate(source: &str, segments: &[PathSegment]) -> (usize, usize) {
+    let chars: Vec<char> = source.chars().collect();
+    let mut pos = skip_whitespace(&chars, 0);
+    for segment in segments {
+        match find_child(&chars, pos, segment) {
+            Some(child) => pos = child,
+            None => break,
+        }
+    }
+
+    let byte_offset: usize = chars[..pos.min(chars.len())]
+        .iter()
+        .map(|c| c.len_utf8())
+        .sum();
+    line_column(source, byte_offset)
+}
+
+// 1-based line and column of a byte offset into the source
+pub fn line_column(source: &str, byte_offset: usize) -> (usize, usize) {
+    let mut offset = byte_offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let preceding = &source[..offset];
+    let line = preceding.matches('\n').count() + 1;
+    let column = preceding
+        .rsplit('\n')
+        .next()
+        .map(|line| line.chars().count())
+        .unwrap_or_default()
+        + 1;
+    (line, column)
+}
+
+// the earliest occurrence of the key as a key (followed by ':', '=', '.' or ']')
+fn find_key(text: &str, key: &str) -> Option<usize> {
+    [
+        key.to_string(),
+        format!("\"{}\"", key),
+        format!("'{}'", key),
+    ]
+    .iter()
+    .filter_map(|candidate| {
+        text.match_indices(candidate.as_str())
+            .find(|(start, _)| {
+                let end = start + candidate.len();
+                let starts_key = match text[..*start].chars().next_back() {
+                    Some(c) => c.is_whitespace() || "[{.,-".contains(c),
+                    None => true,
+                };
+                let ends_key = text[end..]
+                    .trim_start_matches([' ', '\t'])
+                    .starts_with([':', '=', '.', ']']);
+                starts_key && ends_key
+            })
+            .map(|(start, _)| start)
+    })
+    .min()
+}
+
+// TOML and YAML files are located by finding each key of the path in turn after the previous
+// one, which matches the usual one setting per line layout
+pub fn locate_keys(source: &str, segments: &[PathSegment]) -> (usize, usize) {
+    let mut offset = 0;
+    for segment in segments {
+        if let PathSegment::Key(key) = segment {
+            match find_key(&source[offset..], key) {
+                Some(found) => offset += found,
+                None => break,
+            }
+        }
+    }
+    line_column(source, offset)
+}
+
+fn diagnostic<L>(locate: &L, segments: &[PathSegment], message: String) -> ConfigDiagnostic
+where
+    L: Fn(&[PathSegment]) -> (usize, usize),
+{
+    let (line, column) = locate(segments);
+    ConfigDiagnostic {
+        file: None,
+        path: path_to_string(segments),
+        line,
+        column,
+        message,
+    }
+}
+
+fn error_segments(path: &serde_path_to_error::Path) -> Vec<PathSegment> {
+    path.iter()
+        .filter_map(|segment| match segment {
+            serde_path_to_error::Segment::Seq { index } => Some(PathSegment::Index(*index)),
+            serde_path_to_error::Segment::Map { key } => Some(PathSegment::Key(key.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+fn ignored_segments(path: &serde_ignored::Path) -> Vec<PathSegment> {
+    use serde_ignored::Path;
+
+    let mut segments = match path {
+        Path::Root => return Vec::new(),
+        Path::Seq { parent, .. }
+        | Path::Map { parent, .. }
+        | Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => ignored_segments(parent),
+    };
+    match path {
+        Path::Seq { index, .. } => segments.push(PathSegment::Index(*index)),
+        Path::Map { key, .. } => segments.push(PathSegment::Key(key.clone())),
+        _ => {}
+    }
+    segments
+}
+
+fn remove_value(value: &mut Value, segments: &[PathSegment]) -> bool {
+    let Some((last, parents)) = segments.split_last() else {
+        return false;
+    };
+    let mut parent = value;
+    for segment in parents {
+        let child = match (parent, segment) {
+            (Value::Object(map), PathSegment::Key(key)) => map.get_mut(key),
+            (Value::Array(array), PathSegment::Index(index)) => array.get_mut(*index),
+            _ => None,
+        };
+        match child {
+            Some(child) => parent = child,
+            None => return false,
+        }
+    }
+    match (parent, last) {
+        (Value::Object(map), PathSegment::Key(key)) => map.remove(key).is_some(),
+        (Value::Array(array), PathSegment::Index(index)) if *index < array.len() => {
+            array.remove(*index);
+            true
+        }
+        _ => false,
+    }
+}
+
+// serde_json, toml and serde_yaml append the position to its messages, the diagnostic already carries it
+pub fn strip_position(message: String) -> String {
+    match message.rsplit_once(" at line ") {
+        Some((message, _)) => message.to_string(),
+        None => message,
+    }
+}
+
+// serde reports missing fields as "missing field `name`"
+fn missing_field(message: &str) -> Option<String> {
+    let rest = message.strip_prefix("missing field `")?;
+    rest.split_once('`').map(|(field, _)| field.to_string())
+}
+
+pub fn parse_json(source: &str) -> Result<Value, Vec<ConfigDiagnostic>> {
+    serde_json::from_str(source).map_err(|e| {
+        Vec::from([ConfigDiagnostic {
+            file: None,
+            path: String::new(),
+            line: e.line(),
+            column: e.column(),
+            message: strip_position(e.to_string()),
+        }])
+    })
+}
+
+// Deserializes a value read from a config file, reporting every invalid setting rather than
+// only the first one. Unknown keys don't fail the file, they are returned as warnings.
+// locate gives the line and column of a path in the file
+pub fn deserialize_with_diagnostics<T, L>(
+    mut value: Value,
+    locate: L,
+) -> Result<(T, Vec<ConfigDiagnostic>), Vec<ConfigDiagnostic>>
+where
+    T: DeserializeOwned,
+    L: Fn(&[PathSegment]) -> (usize, usize),
+{
+    let mut errors = Vec::new();
+    let mut removed: Vec<Vec<PathSegment>> = Vec::new();
+    while errors.len() < MAX_DIAGNOSTICS {
+        let mut unknown_keys = Vec::new();
+        let mut record_unknown_key =
+            |path: serde_ignored::Path| unknown_keys.push(ignored_segments(&path));
+        let deserializer = serde_ignored::Deserializer::new(value.clone(), &mut record_unknown_key);
+        match serde_path_to_error::deserialize::<_, T>(deserializer) {
+            Ok(config) if errors.is_empty() => {
+                let warnings = unknown_keys
+                    .iter()
+                    .map(|segments| {
+                        diagnostic(&locate, segments, String::from("unknown setting, ignored"))
+                    })
+                    .collect();
+                return Ok((config, warnings));
+            }
+            Ok(_) => break,
+            Err(e) => {
+                let segments = error_segments(e.path());
+                let message = strip_position(e.inner().to_string());
+                if let Some(field) = missing_field(&message) {
+                    // a setting that was dropped below is required, it has already been reported
+                    let mut field_path = segments.clone();
+                    field_path.push(PathSegment::Key(field));
+                    if !removed.contains(&field_path) {
+                        errors.push(diagnostic(&locate, &segments, message));
+                    }
+                    // dropping the parent of a missing field only moves the problem up a level
+                    break;
+                }
+                errors.push(diagnostic(&locate, &segments, message));
+                // drop the invalid setting and look for the next problem
+                if !remove_value(&mut value, &segments) {
+                    break;
+                }
+                removed.push(segments);
+            }
+        }
+    }
+
+    Err(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Settings {
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        size: u32,
+        #[serde(default)]
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn locate_finds_nested_values() {
+        let source = "{\n  \"a\": {\"b\": [1, {\"c\": true}]},\n  \"d\": \"x\"\n}";
+
+        assert_eq!(
+            locate(
+                source,
+                &[
+                    PathSegment::Key("a".to_string()),
+                    PathSegment::Key("b".to_string()),
+                    PathSegment::Index(1),
+                    PathSegment::Key("c".to_string()),
+                ]
+            ),
+            (2, 24)
+        );
+        assert_eq!(locate(source, &[PathSegment::Key("d".to_string())]), (3, 8));
+        assert_eq!(
+            locate(source, &[PathSegment::Key("missing".to_string())]),
+            (1, 1)
+        );
+    }
+
+    #[test]
+    fn deserialize_with_diagnostics_reports_every_invalid_setting() {
+        let source = "{\n  \"name\": 5,\n  \"size\": \"big\",\n  \"tags\": [\"ok\"]\n}";
+
+        let actual =
+            deserialize_with_diagnostics::<Settings, _>(parse_json(source).unwrap(), |segments| {
+                locate(source, segments)
+            })
+            .unwrap_err();
+
+        assert_eq!(actual.len(), 2);
+        assert_eq!(actual[0].path, "name");
+        assert_eq!((actual[0].line, actual[0].column), (2, 11));
+        assert_eq!(actual[1].path, "size");
+        assert_eq!((actual[1].line, actual[1].column), (3, 11));
+        assert!(actual[1].message.starts_with("invalid type"));
+    }
+
+    #[test]
+    fn deserialize_with_diagnostics_warns_about_unknown_keys() {
+        let source = "{\"name\": \"a\", \"colour\": \"red\"}";
+
+        let (actual, warnings) =
+            deserialize_with_diagnostics::<Settings, _>(parse_json(source).unwrap(), |segments| {
+                locate(source, segments)
+            })
+            .unwrap();
+
+        assert_eq!(actual.name, "a");
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].path, "colour");
+        assert_eq!((warnings[0].line, warnings[0].column), (1, 25));
+    }
+
+    #[test]
+    fn locate_keys_finds_toml_and_yaml_settings() {
+        let toml = "[shell]\nprogram = \"fish\"\n\n[keymaps]\n\"edit:copy\" = \"ctrl+c\"\n";
+        let yaml = "shell:\n  programs: []\n  program: fish\n";
+        let path = |keys: &[&str]| -> Vec<PathSegment> {
+            keys.iter()
+                .map(|key| PathSegment::Key(key.to_string()))
+                .collect()
+        };
+
+        assert_eq!(locate_keys(toml, &path(&["shell", "program"])), (2, 1));
+        assert_eq!(locate_keys(toml, &path(&["keymaps", "edit:copy"])), (5, 1));
+        assert_eq!(locate_keys(yaml, &path(&["shell", "program"])), (3, 3));
+    }
+
+    #[test]
+    fn parse_json_reports_syntax_errors() {
+        let source = "{\n  \"name\": \"a\",\n}";
+
+        let actual = parse_json(source).unwrap_err();
+
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual[0].path, "");
+        assert_eq!(actual[0].line, 3);
+        assert!(!actual[0].message.contains("at line"));
+    }
+}
@@ -0,0 +1,172 @@
+use serde_json::Value;
+use std::path::Path;
+
+use crate::config_diagnostics::{self, ConfigDiagnostic, PathSegment};
+
+// The file formats a config can be written in, picked by file extension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    // anything without a .toml, .yaml or .yml extension is read as JSON
+    pub fn from_path(file_loc: &str) -> ConfigFormat {
+        let extension = Path::new(file_loc)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("toml") => ConfigFormat::Toml,
+            Some("yaml") | Some("yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Json,
+        }
+    }
+
+    pub fn parse(self, source: &str) -> Result<Value, Vec<ConfigDiagnostic>> {
+        match self {
+            ConfigFormat::Json => config_diagnostics::parse_json(source),
+            ConfigFormat::Toml => toml::from_str(source).map_err(|e| {
+                let (line, column) = e
+                    .span()
+                    .map(|span| config_diagnostics::line_column(source, span.start))
+                    .unwrap_or((1, 1));
+                Vec::from([ConfigDiagnostic {
+                    file: None,
+                    path: String::new(),
+                    line,
+                    column,
+                    message: e.message().to_string(),
+                }])
+            }),
+            ConfigFormat::Yaml => match serde_yaml::from_str(source) {
+                // an empty YAML document is null, treat it like an empty file in the other formats
+                Ok(Value::Null) => Ok(Value::Object(serde_json::Map::new())),
+                Ok(value) => Ok(value),
+                Err(e) => {
+                    let (line, column) = e
+                        .location()
+                        .map(|location| (location.line(), location.column()))
+                        .unwrap_or((1, 1));
+                    Err(Vec::from([ConfigDiagnostic {
+                        file: None,
+                        path: String::new(),
+                        line,
+                        column,
+                        message: config_diagnostics::strip_position(e.to_string()),
+                    }]))
+                }
+            },
+        }
+    }
+
+    pub fn locate(self, source: &str, segments: &[PathSegment]) -> (usize, usize) {
+        match self {
+            ConfigFormat::Json => config_diagnostics::locate(source, segments),
+            ConfigFormat::Toml | ConfigFormat::Yaml => {
+                config_diagnostics::locate_keys(source, segments)
+            }
+        }
+    }
+
+    // TOML and YAML have no "$schema" key, editors read the schema location from a comment
+    pub fn serialize(self, value: &Value) -> Result<String, String> {
+        let mut value = value.clone();
+        let schema = match value.as_object_mut() {
+            Some(object) if self != ConfigFormat::Json => object.remove("$schema"),
+            _ => None,
+        };
+        let schema = schema.as_ref().and_then(Value::as_str);
+
+        match self {
+            ConfigFormat::Json => serde_json::to_string_pretty(&value).map_err(|e| e.to_string()),
+            ConfigFormat::Toml => {
+                // TOML has no null, an unset setting is just left out
+                remove_nulls(&mut value);
+                let toml = toml::to_string_pretty(&value).map_err(|e| e.to_string())?;
+                Ok(match schema {
+                    Some(schema) => format!("#:schema {}\n\n{}", schema, toml),
+                    None => toml,
+                })
+            }
+            ConfigFormat::Yaml => {
+                let yaml = serde_yaml::to_string(&value).map_err(|e| e.to_string())?;
+                Ok(match schema {
+                    Some(schema) => format!("# yaml-language-server: $schema={}\n{}", schema, yaml),
+                    None => yaml,
+                })
+            }
+        }
+    }
+}
+
+fn remove_nulls(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            object.retain(|_, value| !value.is_null());
+            object.values_mut().for_each(remove_nulls);
+        }
+        Value::Array(array) => array.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_path_uses_the_file_extension() {
+        assert_eq!(ConfigFormat::from_path("a/config.toml"), ConfigFormat::Toml);
+        assert_eq!(ConfigFormat::from_path("config.YML"), ConfigFormat::Yaml);
+        assert_eq!(ConfigFormat::from_path("config.yaml"), ConfigFormat::Yaml);
+        assert_eq!(
+            ConfigFormat::from_path(".alphacentauri.config.json"),
+            ConfigFormat::Json
+        );
+        assert_eq!(ConfigFormat::from_path("config"), ConfigFormat::Json);
+    }
+
+    #[test]
+    fn serialize_round_trips_through_each_format() {
+        let value = serde_json::json!({
+            "$schema": "./schema.json",
+            "defaultProfile": null,
+            "shell": {"program": "fish", "args": ["-l"], "bell": false},
+            "keymaps": {"edit:copy": "ctrl+c"}
+        });
+        let mut expected = value.clone();
+        expected.as_object_mut().unwrap().remove("$schema");
+
+        let toml = ConfigFormat::Toml.serialize(&value).unwrap();
+        assert!(toml.starts_with("#:schema ./schema.json\n"));
+        let mut without_null = expected.clone();
+        without_null
+            .as_object_mut()
+            .unwrap()
+            .remove("defaultProfile");
+        assert_eq!(ConfigFormat::Toml.parse(&toml).unwrap(), without_null);
+
+        let yaml = ConfigFormat::Yaml.serialize(&value).unwrap();
+        assert!(yaml.starts_with("# yaml-language-server: $schema=./schema.json\n"));
+        assert_eq!(ConfigFormat::Yaml.parse(&yaml).unwrap(), expected);
+
+        let json = ConfigFormat::Json.serialize(&value).unwrap();
+        assert_eq!(ConfigFormat::Json.parse(&json).unwrap(), value);
+    }
+
+    #[test]
+    fn parse_reports_syntax_error_positions() {
+        let actual = ConfigFormat::Toml
+            .parse("[shell]\nprogram = \"fish\nbell = true\n")
+            .unwrap_err();
+        assert_eq!(actual[0].line, 2);
+
+        let actual = ConfigFormat::Yaml
+            .parse("shell:\n  program: fish\n bell: true\n")
+            .unwrap_err();
+        assert_eq!(actual[0].line, 3);
+        assert!(!actual[0].message.contains("at line"));
+    }
+}
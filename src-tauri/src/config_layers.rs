@@ -0,0 +1,791 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use dir::home_dir;
+
+use crate::{
+    config_diagnostics::{self, ConfigDiagnostic, PathSegment},
+    config_format::ConfigFormat,
+    project_trust::{self, UntrustedProject},
+    usr_conf::{self, UserConfigError, UserConfigFS},
+};
+
+#[cfg(not(target_os = "windows"))]
+const SYSTEM_CONFIG_DIR: &str = "/etc/alpha-centauri";
+
+const CONFIG_EXTENSIONS: &[&str] = &["json", "toml", "yaml", "yml"];
+const SYSTEM_CONFIG_STEM: &str = "config";
+const PROJECT_CONFIG_STEM: &str = ".alphacentauri.config";
+const INCLUDE_KEY: &str = "include";
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+// Where a setting came from, from lowest to highest priority. Included files sit just
+// beneath the file that includes them
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ConfigLayerKind {
+    Default,
+    System,
+    User,
+    Include,
+    Project,
+    CommandLine,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigSource {
+    pub kind: ConfigLayerKind,
+    // the file, or the --set argument, the setting was read from
+    pub location: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigLayer {
+    pub source: ConfigSource,
+    // the settings exactly as written
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigSources {
+    // in the order they were applied, the built-in defaults are not listed
+    pub layers: Vec<ConfigLayer>,
+    // dotted setting path to the layer that set its value
+    pub values: BTreeMap<String, ConfigSource>,
+}
+
+// The files and overrides resolved into the config, besides the built-in defaults
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigLayerFiles {
+    pub system: Option<String>,
+    pub user: String,
+    pub project: Option<String>,
+    // `key=value` arguments given with --set
+    pub overrides: Vec<String>,
+    // the project files the user trusted, in the state directory
+    pub trusted_projects: Option<PathBuf>,
+}
+
+impl ConfigLayerFiles {
+    pub fn files(&self) -> Vec<&str> {
+        [
+            self.system.as_deref(),
+            Some(self.user.as_str()),
+            self.project.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct LayeredConfig {
+    pub config: UserConfigFS,
+    pub sources: ConfigSources,
+    pub warnings: Vec<ConfigDiagnostic>,
+    // what upgrading the user's file from an older version would change
+    pub migrations: Vec<String>,
+    // the project file's settings that were left out until the user trusts it
+    pub untrusted_project: Option<UntrustedProject>,
+}
+
+pub fn find_config_file(dir: &Path, stem: &str) -> Option<PathBuf> {
+    CONFIG_EXTENSIONS
+        .iter()
+        .map(|extension| dir.join(format!("{}.{}", stem, extension)))
+        .find(|path| path.is_file())
+}
+
+// config.json (or .toml, .yaml) under /etc/alpha-centauri, or %ProgramData%\alpha-centauri
+pub fn system_config_file() -> Option<String> {
+    #[cfg(target_os = "windows")]
+    let dir = PathBuf::from(std::env::var_os("ProgramData")?).join("alpha-centauri");
+    #[cfg(not(target_os = "windows"))]
+    let dir = PathBuf::from(SYSTEM_CONFIG_DIR);
+
+    find_config_file(&dir, SYSTEM_CONFIG_STEM).map(|path| path.to_string_lossy().to_string())
+}
+
+// The closest .alphacentauri.config.* in start or one of its parents below the home directory.
+// Older versions kept the user's file in the home directory under the same name, so a file
+// there is never a project's. The user's own file is skipped wherever it is
+pub fn find_project_config(start: &Path, user_file: &str, home: Option<&Path>) -> Option<String> {
+    let user_file = fs::canonicalize(user_file).ok();
+    let home = home.and_then(|home| fs::canonicalize(home).ok());
+    start
+        .ancestors()
+        .take_while(|dir| home.is_none() || fs::canonicalize(dir).ok() != home)
+        .filter_map(|dir| find_config_file(dir, PROJECT_CONFIG_STEM))
+        .find(|path| fs::canonicalize(path).ok() != user_file)
+        .map(|path| path.to_string_lossy().to_string())
+}
+
+// `shell.program=fish` becomes {"shell":{"program":"fish"}}. The value is read as JSON when it
+// parses, so `shell.bell=false` and `shell.args=["-l"]` work, and as a plain string otherwise
+pub fn parse_override(arg: &str) -> Result<Value, String> {
+    let (key, raw_value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected key=value, found \"{}\"", arg))?;
+    let key = key.trim();
+    if key.is_empty() || key.split('.').any(str::is_empty) {
+        return Err(format!("invalid setting name \"{}\"", key));
+    }
+
+    let mut value = serde_json::from_str(raw_value).unwrap_or(Value::String(raw_value.to_string()));
+    for part in key.rsplit('.') {
+        let mut object = Map::new();
+        object.insert(part.to_string(), value);
+        value = Value::Object(object);
+    }
+    Ok(value)
+}
+
+fn child_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn record_sources(
+    value: &Value,
+    source: &ConfigSource,
+    path: &str,
+    values: &mut BTreeMap<String, ConfigSource>,
+) {
+    match value {
+        Value::Object(object) if !object.is_empty() => {
+            for (key, child) in object {
+                record_sources(child, source, &child_path(path, key), values);
+            }
+        }
+        _ => {
+            values.insert(path.to_string(), source.clone());
+        }
+    }
+}
+
+// usr_conf::merge_json, recording the source of every value the overlay sets
+fn merge_layer(
+    base: &mut Value,
+    overlay: Value,
+    source: &ConfigSource,
+    path: &str,
+    values: &mut BTreeMap<String, ConfigSource>,
+) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                let path = child_path(path, &key);
+                match base.get_mut(&key) {
+                    Some(existing) => merge_layer(existing, value, source, &path, values),
+                    None => {
+                        record_sources(&value, source, &path, values);
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => {
+            let nested = format!("{}.", path);
+            values.retain(|key, _| key != path && !key.starts_with(&nested));
+            record_sources(&overlay, source, path, values);
+            *base = overlay;
+        }
+    }
+}
+
+fn without_includes(value: &Value) -> Value {
+    let mut value = value.clone();
+    if let Some(object) = value.as_object_mut() {
+        object.remove(INCLUDE_KEY);
+    }
+    value
+}
+
+fn includes(value: &Value) -> Vec<String> {
+    value
+        .get(INCLUDE_KEY)
+        .and_then(Value::as_array)
+        .map(|includes| {
+            includes
+                .iter()
+                .filter_map(Value::as_str)
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn resolve_include(include: &str, file_loc: &str) -> Option<PathBuf> {
+    if let Some(rest) = include.strip_prefix("~/") {
+        return home_dir().map(|home| home.join(rest));
+    }
+    let path = PathBuf::from(include);
+    if path.is_absolute() {
+        Some(path)
+    } else {
+        Some(Path::new(file_loc).parent()?.join(path))
+    }
+}
+
+fn in_file(e: UserConfigError, file_loc: &str) -> UserConfigError {
+    match e {
+        UserConfigError::Invalid(diagnostics) => UserConfigError::Invalid(
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| ConfigDiagnostic {
+                    file: Some(file_loc.to_string()),
+                    ..diagnostic
+                })
+                .collect(),
+        ),
+        UserConfigError::Read(e) => UserConfigError::Read(format!("{}: {}", file_loc, e)),
+        e => e,
+    }
+}
+
+fn include_warning(file_loc: &str, message: String) -> ConfigDiagnostic {
+    let source = fs::read_to_string(file_loc).unwrap_or_default();
+    let (line, column) = ConfigFormat::from_path(file_loc)
+        .locate(&source, &[PathSegment::Key(INCLUDE_KEY.to_string())]);
+    ConfigDiagnostic {
+        file: Some(file_loc.to_string()),
+        path: INCLUDE_KEY.to_string(),
+        line,
+        column,
+        message,
+    }
+}
+
+// Adds the file and everything it includes to the layers, included files first so the
+// including file wins. Each file is checked on its own, so problems point into that file
+fn load_file_layers(
+    file_loc: &str,
+    kind: ConfigLayerKind,
+    chain: &mut Vec<PathBuf>,
+    layers: &mut Vec<ConfigLayer>,
+    warnings: &mut Vec<ConfigDiagnostic>,
+) -> usr_conf::Result<()> {
+    let (value, _, file_warnings) =
+        usr_conf::read_user_configuration(file_loc).map_err(|e| in_file(e, file_loc))?;
+    warnings.extend(file_warnings.into_iter().map(|warning| ConfigDiagnostic {
+        file: Some(file_loc.to_string()),
+        ..warning
+    }));
+
+    chain.push(fs::canonicalize(file_loc).unwrap_or(PathBuf::from(file_loc)));
+    for include in includes(&value) {
+        let Some(include_loc) = resolve_include(&include, file_loc).filter(|p| p.is_file()) else {
+            warnings.push(include_warning(
+                file_loc,
+                format!("included file {} not found", include),
+            ));
+            continue;
+        };
+        let canonical = fs::canonicalize(&include_loc).unwrap_or(include_loc.clone());
+        if chain.contains(&canonical) || chain.len() > MAX_INCLUDE_DEPTH {
+            warnings.push(include_warning(
+                file_loc,
+                format!("{} was not included, it would include itself", include),
+            ));
+            continue;
+        }
+        load_file_layers(
+            &include_loc.to_string_lossy(),
+            ConfigLayerKind::Include,
+            chain,
+            layers,
+            warnings,
+        )?;
+    }
+    chain.pop();
+
+    layers.push(ConfigLayer {
+        source: ConfigSource {
+            kind,
+            location: Some(file_loc.to_string()),
+        },
+        value,
+    });
+    Ok(())
+}
+
+// the source of a setting, or of the closest setting above or below it
+fn source_of<'a>(
+    values: &'a BTreeMap<String, ConfigSource>,
+    path: &str,
+) -> Option<&'a ConfigSource> {
+    let path = path.split('[').next().unwrap_or_default();
+    let mut parent = path;
+    loop {
+        if let Some(source) = values.get(parent) {
+            return Some(source);
+        }
+        match parent.rsplit_once('.') {
+            Some((rest, _)) => parent = rest,
+            None => break,
+        }
+    }
+    let nested = format!("{}.", path);
+    values
+        .iter()
+        .find(|(key, _)| key.starts_with(&nested))
+        .map(|(_, source)| source)
+}
+
+fn merge_layers(layers: &[ConfigLayer]) -> (Value, BTreeMap<String, ConfigSource>) {
+    let mut values = BTreeMap::new();
+    let mut merged =
+        serde_json::to_value(usr_conf::generate_default_user_config()).unwrap_or_default();
+    let default_source = ConfigSource {
+        kind: ConfigLayerKind::Default,
+        location: None,
+    };
+    record_sources(&merged, &default_source, "", &mut values);
+    for layer in layers {
+        merge_layer(
+            &mut merged,
+            without_includes(&layer.value),
+            &layer.source,
+            "",
+            &mut values,
+        );
+    }
+    (merged, values)
+}
+
+fn resolve_layers(
+    layers: Vec<ConfigLayer>,
+    mut warnings: Vec<ConfigDiagnostic>,
+) -> usr_conf::Result<LayeredConfig> {
+    let (merged, values) = merge_layers(&layers);
+    let attribute = |diagnostic: ConfigDiagnostic| {
+        let source = source_of(&values, &diagnostic.path);
+        let file = source.and_then(|source| match source.kind {
+            ConfigLayerKind::CommandLine => {
+                source.location.as_ref().map(|arg| format!("--set {}", arg))
+            }
+            _ => source.location.clone(),
+        });
+        (
+            source.map(|source| source.kind),
+            ConfigDiagnostic { file, ..diagnostic },
+        )
+    };
+
+    // each file was already checked on its own, so anything found here comes from combining
+    // layers or from the command line, which has no position to report
+    let (mut config, combined_warnings) =
+        config_diagnostics::deserialize_with_diagnostics::<UserConfigFS, _>(merged, |_| (1, 1))
+            .map_err(|errors| {
+                UserConfigError::Invalid(errors.into_iter().map(|e| attribute(e).1).collect())
+            })?;
+    warnings.extend(
+        combined_warnings
+            .into_iter()
+            .map(attribute)
+            .filter(|(kind, _)| *kind == Some(ConfigLayerKind::CommandLine))
+            .map(|(_, warning)| warning),
+    );
+
+    config.include = Vec::new();
+    Ok(LayeredConfig {
+        config,
+        sources: ConfigSources { layers, values },
+        warnings,
+        migrations: Vec::new(),
+        untrusted_project: None,
+    })
+}
+
+// Takes the settings that run programs or grant permissions out of a project file and the
+// files it includes, unless the user trusted their exact content
+fn check_project_trust(
+    file_loc: &str,
+    project_layers: &mut [ConfigLayer],
+    trust_file: Option<&Path>,
+) -> Option<UntrustedProject> {
+    let locations: Vec<&str> = project_layers
+        .iter()
+        .filter_map(|layer| layer.source.location.as_deref())
+        .collect();
+    let hash = project_trust::content_hash(&locations);
+    if project_trust::is_trusted(trust_file, file_loc, &hash) {
+        return None;
+    }
+
+    let mut ignored = Vec::new();
+    for layer in project_layers.iter_mut() {
+        for path in project_trust::remove_untrusted_settings(&mut layer.value) {
+            if !ignored.contains(&path) {
+                ignored.push(path);
+            }
+        }
+    }
+    if ignored.is_empty() {
+        return None;
+    }
+    Some(UntrustedProject {
+        file: file_loc.to_string(),
+        hash,
+        ignored,
+    })
+}
+
+pub fn resolve_configuration(
+    files: &ConfigLayerFiles,
+    save_default_config: bool,
+) -> usr_conf::Result<LayeredConfig> {
+    if save_default_config && fs::metadata(&files.user).is_err() {
+        usr_conf::get_user_configuration(&files.user, true)?;
+    }
+
+    let mut layers = Vec::new();
+    let mut warnings = Vec::new();
+    // every file is migrated as it is read, the user's file is only upgraded on disk when the
+    // user asks
+    let migrations = usr_conf::pending_migrations(&files.user);
+    let file_layers = [
+        (files.system.as_deref(), ConfigLayerKind::System),
+        (Some(files.user.as_str()), ConfigLayerKind::User),
+        (files.project.as_deref(), ConfigLayerKind::Project),
+    ];
+    let mut untrusted_project = None;
+    for (file_loc, kind) in file_layers {
+        let Some(file_loc) = file_loc.filter(|file_loc| fs::metadata(file_loc).is_ok()) else {
+            continue;
+        };
+        if kind != ConfigLayerKind::Project {
+            load_file_layers(file_loc, kind, &mut Vec::new(), &mut layers, &mut warnings)?;
+            continue;
+        }
+        let mut project_layers = Vec::new();
+        load_file_layers(
+            file_loc,
+            kind,
+            &mut Vec::new(),
+            &mut project_layers,
+            &mut warnings,
+        )?;
+        untrusted_project = check_project_trust(
+            file_loc,
+            &mut project_layers,
+            files.trusted_projects.as_deref(),
+        );
+        layers.extend(project_layers);
+    }
+    for arg in files.overrides.iter() {
+        let value = parse_override(arg).map_err(|message| {
+            UserConfigError::Invalid(Vec::from([ConfigDiagnostic {
+                file: Some(format!("--set {}", arg)),
+                path: String::new(),
+                line: 1,
+                column: 1,
+                message,
+            }]))
+        })?;
+        layers.push(ConfigLayer {
+            source: ConfigSource {
+                kind: ConfigLayerKind::CommandLine,
+                location: Some(arg.clone()),
+            },
+            value,
+        });
+    }
+
+    let mut layered = resolve_layers(layers, warnings)?;
+    layered.migrations = migrations;
+    layered.untrusted_project = untrusted_project;
+    Ok(layered)
+}
+
+// Updates the settings written in the file to match the config, dropping nothing the user
+// wrote that the config still has
+fn refresh_written_settings(written: &Value, config: &Value) -> Option<Value> {
+    match (written, config) {
+        (Value::Object(written), Value::Object(config)) => Some(Value::Object(
+            written
+                .iter()
+                .filter_map(|(key, value)| {
+                    let refreshed = match config.get(key) {
+                        Some(config_value) => refresh_written_settings(value, config_value)?,
+                        // not a setting of the resolved config, such as include
+                        None if key == INCLUDE_KEY => value.clone(),
+                        None => return None,
+                    };
+                    Some((key.clone(), refreshed))
+                })
+                .collect(),
+        )),
+        _ => Some(config.clone()),
+    }
+}
+
+// the parts of value that differ from base
+fn diff_json(base: &Value, value: &Value) -> Option<Value> {
+    match (base, value) {
+        (Value::Object(base), Value::Object(value)) => {
+            let diff: Map<String, Value> = value
+                .iter()
+                .filter_map(|(key, value)| match base.get(key) {
+                    Some(base_value) => diff_json(base_value, value).map(|d| (key.clone(), d)),
+                    None => Some((key.clone(), value.clone())),
+                })
+                .collect();
+            if diff.is_empty() {
+                None
+            } else {
+                Some(Value::Object(diff))
+            }
+        }
+        (base, value) if base == value => None,
+        _ => Some(value.clone()),
+    }
+}
+
+// What to write to the user's file so the layers resolve to config. Settings already in the
+// file are kept, anything else is only written when no other layer provides that value, so
+// fleet defaults from the system file keep applying to settings the user never changed
+pub fn user_file_settings(sources: &ConfigSources, config: &UserConfigFS) -> Value {
+    let other_layers: Vec<ConfigLayer> = sources
+        .layers
+        .iter()
+        .filter(|layer| layer.source.kind != ConfigLayerKind::User)
+        .cloned()
+        .collect();
+    let (base, _) = merge_layers(&other_layers);
+    let config = serde_json::to_value(config).unwrap_or_default();
+
+    let written = sources
+        .layers
+        .iter()
+        .find(|layer| layer.source.kind == ConfigLayerKind::User)
+        .map(|layer| layer.value.clone())
+        .unwrap_or(Value::Object(Map::new()));
+    let mut settings =
+        refresh_written_settings(&written, &config).unwrap_or(Value::Object(Map::new()));
+    if let Some(diff) = diff_json(&base, &config) {
+        usr_conf::merge_json(&mut settings, diff);
+    }
+    settings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn parse_override_builds_nested_settings() {
+        assert_eq!(
+            parse_override("shell.bell=false").unwrap(),
+            serde_json::json!({"shell": {"bell": false}})
+        );
+        assert_eq!(
+            parse_override("keymaps.edit:copy=ctrl+c").unwrap(),
+            serde_json::json!({"keymaps": {"edit:copy": "ctrl+c"}})
+        );
+        assert!(parse_override("shell.program").is_err());
+        assert!(parse_override("shell..program=fish").is_err());
+    }
+
+    #[test]
+    fn find_project_config_walks_up_and_skips_the_user_file() {
+        let dir = TempDir::new("usr_project").unwrap();
+        let nested = dir.path().join("src").join("bin");
+        fs::create_dir_all(&nested).unwrap();
+        let project_file = dir.path().join(".alphacentauri.config.toml");
+        fs::write(&project_file, "").unwrap();
+        let project_file_str = project_file.to_string_lossy().to_string();
+
+        assert_eq!(
+            find_project_config(&nested, "/nonexistent/user.json", None),
+            Some(project_file_str.clone())
+        );
+        assert_eq!(find_project_config(&nested, &project_file_str, None), None);
+
+        let _ = dir.close();
+    }
+
+    #[test]
+    fn find_project_config_stops_before_the_home_directory() {
+        let home = TempDir::new("usr_home").unwrap();
+        let project = home.path().join("src").join("app");
+        fs::create_dir_all(&project).unwrap();
+        // left behind by an older version, the user now has an XDG config
+        fs::write(home.path().join(".alphacentauri.config.json"), "{}").unwrap();
+
+        assert_eq!(
+            find_project_config(&project, "/nonexistent/user.json", Some(home.path())),
+            None
+        );
+        assert_eq!(
+            find_project_config(home.path(), "/nonexistent/user.json", Some(home.path())),
+            None
+        );
+
+        let project_file = project.join(".alphacentauri.config.yaml");
+        fs::write(&project_file, "").unwrap();
+        assert_eq!(
+            find_project_config(&project, "/nonexistent/user.json", Some(home.path())),
+            Some(project_file.to_string_lossy().to_string())
+        );
+
+        let _ = home.close();
+    }
+
+    #[test]
+    fn resolve_configuration_merges_layers_in_order() {
+        let dir = TempDir::new("usr_home").unwrap();
+        let system = dir.path().join("system.toml");
+        let shared = dir.path().join("shared.yaml");
+        let user = dir.path().join("user.json");
+        let project = dir.path().join(".alphacentauri.config.json");
+        fs::write(
+            &system,
+            "[shell]\nprogram = \"bash\"\nfonts = \"Fleet Mono\"\n",
+        )
+        .unwrap();
+        fs::write(&shared, "shell:\n  bell: false\n  program: zsh\n").unwrap();
+        fs::write(
+            &user,
+            "{\"include\": [\"shared.yaml\", \"missing.json\"], \"shell\": {\"program\": \"fish\"}}",
+        )
+        .unwrap();
+        fs::write(&project, "{\"shell\": {\"args\": [\"-l\"]}}").unwrap();
+        let project_str = project.to_string_lossy().to_string();
+        let trust_file = dir.path().join("trusted_projects.json");
+        project_trust::trust_project(
+            &trust_file,
+            &project_str,
+            &project_trust::content_hash(&[&project_str]),
+        )
+        .unwrap();
+        let files = ConfigLayerFiles {
+            system: Some(system.to_string_lossy().to_string()),
+            user: user.to_string_lossy().to_string(),
+            project: Some(project_str),
+            overrides: Vec::from([String::from("shell.changeDirectoryOscCode=1337")]),
+            trusted_projects: Some(trust_file),
+        };
+
+        let actual = resolve_configuration(&files, false).unwrap();
+
+        assert_eq!(actual.config.shell.program, "fish");
+        // the unversioned system file is migrated as it is read
+        assert_eq!(
+            actual.config.font.family,
+            Vec::from([String::from("Fleet Mono")])
+        );
+        assert!(!actual.config.shell.bell);
+        assert_eq!(actual.config.shell.args, Vec::from([String::from("-l")]));
+        assert_eq!(actual.config.shell.change_directory_osc_code, 1337);
+        let kind = |path: &str| actual.sources.values.get(path).map(|source| source.kind);
+        assert_eq!(kind("shell.program"), Some(ConfigLayerKind::User));
+        assert_eq!(kind("font.family"), Some(ConfigLayerKind::System));
+        assert_eq!(kind("shell.bell"), Some(ConfigLayerKind::Include));
+        assert_eq!(kind("shell.args"), Some(ConfigLayerKind::Project));
+        assert_eq!(
+            kind("shell.changeDirectoryOscCode"),
+            Some(ConfigLayerKind::CommandLine)
+        );
+        assert_eq!(kind("clipboard.read"), Some(ConfigLayerKind::Default));
+        assert_eq!(actual.warnings.len(), 1);
+        assert_eq!(actual.warnings[0].path, "include");
+
+        let _ = dir.close();
+    }
+
+    #[test]
+    fn resolve_configuration_ignores_untrusted_project_programs() {
+        let dir = TempDir::new("usr_home").unwrap();
+        let user = dir.path().join("user.json");
+        let project = dir.path().join(".alphacentauri.config.json");
+        fs::write(
+            &user,
+            "{\"version\": 3, \"shell\": {\"program\": \"fish\"}}",
+        )
+        .unwrap();
+        fs::write(
+            &project,
+            "{\"shell\": {\"program\": \"./evil.sh\", \"bell\": false}}",
+        )
+        .unwrap();
+        let files = ConfigLayerFiles {
+            user: user.to_string_lossy().to_string(),
+            project: Some(project.to_string_lossy().to_string()),
+            trusted_projects: Some(dir.path().join("trusted_projects.json")),
+            ..Default::default()
+        };
+
+        let actual = resolve_configuration(&files, false).unwrap();
+
+        assert_eq!(actual.config.shell.program, "fish");
+        assert!(!actual.config.shell.bell);
+        let untrusted = actual.untrusted_project.unwrap();
+        assert_eq!(untrusted.file, project.to_string_lossy());
+        assert_eq!(
+            untrusted.ignored,
+            Vec::from([String::from("shell.program")])
+        );
+
+        project_trust::trust_project(
+            files.trusted_projects.as_ref().unwrap(),
+            &untrusted.file,
+            &untrusted.hash,
+        )
+        .unwrap();
+        let trusted = resolve_configuration(&files, false).unwrap();
+        assert_eq!(trusted.config.shell.program, "./evil.sh");
+        assert_eq!(trusted.untrusted_project, None);
+
+        let _ = dir.close();
+    }
+
+    #[test]
+    fn user_file_settings_only_adds_changed_values() {
+        let dir = TempDir::new("usr_home").unwrap();
+        let system = dir.path().join("system.json");
+        let user = dir.path().join("user.json");
+        fs::write(&system, "{\"shell\": {\"fonts\": \"Fleet Mono\"}}").unwrap();
+        fs::write(
+            &user,
+            "{\"version\": 3, \"include\": [], \"shell\": {\"bell\": true, \"program\": \"fish\"}}",
+        )
+        .unwrap();
+        let files = ConfigLayerFiles {
+            system: Some(system.to_string_lossy().to_string()),
+            user: user.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        let resolved = resolve_configuration(&files, false).unwrap();
+        let mut config = resolved.config;
+        config.shell.program = String::from("zsh");
+        config.notifications.long_running_command_seconds = 5;
+
+        let actual = user_file_settings(&resolved.sources, &config);
+
+        assert_eq!(
+            actual,
+            serde_json::json!({
+                "version": 3,
+                "include": [],
+                "shell": {"bell": true, "program": "zsh"},
+                "notifications": {"longRunningCommandSeconds": 5}
+            })
+        );
+
+        let _ = dir.close();
+    }
+}
@@ -0,0 +1,279 @@
+use serde_json::{json, Value};
+
+// bump when the shape of the config changes, and add a migration from the previous version
+pub const CURRENT_CONFIG_VERSION: u64 = 3;
+
+const VERSION_KEY: &str = "version";
+
+// Upgrades a config written for `from` to the next version
+struct Migration {
+    from: u64,
+    description: &'static str,
+    apply: fn(&mut Value),
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 1,
+        description: "Removed settings that were left at their defaults, so they follow system wide settings and future changes to the defaults",
+        apply: remove_version_1_defaults,
+    },
+    Migration {
+        from: 2,
+        description: "Moved shell.fonts to the font section as a list of families, and the fonts of profiles to their font section",
+        apply: move_fonts_to_font_section,
+    },
+];
+
+// Files without a version were written before versioning, which is version 1
+pub fn config_version(value: &Value) -> u64 {
+    value
+        .get(VERSION_KEY)
+        .and_then(Value::as_u64)
+        .unwrap_or(1)
+        .max(1)
+}
+
+// Brings the settings up to the current version, returning a description of each migration
+// that changed them. Settings from a newer version are left alone and reported as an error
+pub fn migrate(value: &mut Value) -> Result<Vec<String>, String> {
+    let version = config_version(value);
+    if version > CURRENT_CONFIG_VERSION {
+        return Err(format!(
+            "written for configuration version {}, this version of Alpha Centauri only understands up to version {}",
+            version, CURRENT_CONFIG_VERSION
+        ));
+    }
+    let Some(object) = value.as_object_mut() else {
+        return Ok(Vec::new());
+    };
+    if version == CURRENT_CONFIG_VERSION && object.contains_key(VERSION_KEY) {
+        return Ok(Vec::new());
+    }
+
+    let mut changes = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= version) {
+        let before = value.clone();
+        (migration.apply)(value);
+        if *value == before {
+            continue;
+        }
+        changes.push(format!(
+            "Version {} to {}: {}",
+            migration.from,
+            migration.from + 1,
+            migration.description
+        ));
+    }
+    if let Some(object) = value.as_object_mut() {
+        object.insert(VERSION_KEY.to_string(), Value::from(CURRENT_CONFIG_VERSION));
+    }
+    Ok(changes)
+}
+
+// Removes every value in settings that equals the value at the same place in defaults,
+// along with objects left empty. Returns true when settings is now empty
+fn remove_matching(settings: &mut Value, defaults: &Value) -> bool {
+    match (settings, defaults) {
+        (Value::Object(settings), Value::Object(defaults)) => {
+            settings.retain(|key, value| match defaults.get(key) {
+                Some(default) => !remove_matching(value, default),
+                None => true,
+            });
+            settings.is_empty()
+        }
+        (settings, defaults) => settings == defaults,
+    }
+}
+
+// Whether every key of the defaults is in the settings, as in a file the app wrote
+fn contains_keys(settings: &Value, defaults: &Value) -> bool {
+    match (settings, defaults) {
+        (Value::Object(settings), Value::Object(defaults)) => defaults
+            .iter()
+            .all(|(key, default)| settings.get(key).is_some_and(|v| contains_keys(v, default))),
+        _ => true,
+    }
+}
+
+// Before version 2 the app wrote every setting to the user's file on first run. The defaults
+// are frozen here, later changes to generate_default_user_config() must not affect this.
+// Only files the app wrote are cleaned up, in a file written by hand a default value was
+// set on purpose
+fn remove_version_1_defaults(value: &mut Value) {
+    #[cfg(target_os = "windows")]
+    let change_window_title_osc_code = 0;
+    #[cfg(not(target_os = "windows"))]
+    let change_window_title_osc_code = 2;
+
+    let defaults = json!({
+        "shell": {
+            "program": "",
+            "args": [],
+            "env": {},
+            "bell": true,
+            "fonts": "Consolas, Monospace",
+            "changeDirectoryOscCode": 7,
+            "changeWindowTitleOscCode": change_window_title_osc_code
+        },
+        "keymaps": {
+            "edit:copy": "ctrl+shift+c",
+            "edit:paste": "ctrl+shift+v",
+            "edit:select_all": "ctrl+shift+a",
+            "edit:interrupt": "ctrl+c",
+            "window:new_tab": "ctrl+shift+t",
+            "window:next_tab": "ctrl+shift+ArrowRight",
+            "window:prev_tab": "ctrl+shift+ArrowLeft",
+            "window:split_right": "ctrl+shift+d",
+            "window:split_down": "ctrl+shift+e"
+        },
+        "notifications": {
+            "longRunningCommandSeconds": 30,
+            "longRunningCommandEnabled": true
+        },
+        "clipboard": {
+            "write": "allow",
+            "read": "prompt"
+        },
+        "hyperlinks": {
+            "allowedSchemes": ["http", "https", "file"]
+        },
+        "profiles": {},
+        "defaultProfile": null,
+        "ssh": {
+            "hosts": {}
+        }
+    });
+    // the first version wrote at least the shell section and the keymaps
+    let written_by_app = json!({
+        "shell": defaults["shell"].clone(),
+        "keymaps": {}
+    });
+    if contains_keys(value, &written_by_app) {
+        remove_matching(value, &defaults);
+    }
+}
+
+// "Fira Code, 'DejaVu Sans Mono', monospace" as a list, without the quotes
+fn split_font_list(fonts: &str) -> Vec<Value> {
+    fonts
+        .split(',')
+        .map(|family| family.trim().trim_matches(|c| c == '"' || c == '\''))
+        .filter(|family| !family.is_empty())
+        .map(Value::from)
+        .collect()
+}
+
+// Version 2 had a single string of fonts in shell and in each profile
+fn move_fonts_to_font_section(value: &mut Value) {
+    let Some(object) = value.as_object_mut() else {
+        return;
+    };
+
+    let shell_fonts = object
+        .get_mut("shell")
+        .and_then(Value::as_object_mut)
+        .and_then(|shell| shell.remove("fonts"));
+    if let Some(fonts) = shell_fonts.as_ref().and_then(Value::as_str) {
+        let font = object
+            .entry("font")
+            .or_insert_with(|| Value::Object(serde_json::Map::new()));
+        if let Some(font) = font.as_object_mut() {
+            font.insert(String::from("family"), Value::from(split_font_list(fonts)));
+        }
+    }
+
+    if let Some(profiles) = object.get_mut("profiles").and_then(Value::as_object_mut) {
+        for profile in profiles.values_mut().filter_map(Value::as_object_mut) {
+            if let Some(fonts) = profile.remove("fonts") {
+                let family = match fonts {
+                    Value::String(fonts) => Value::from(split_font_list(&fonts)),
+                    other => other,
+                };
+                if !family.is_null() {
+                    profile.insert(String::from("font"), json!({ "family": family }));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_upgrades_version_1_files() {
+        let mut value = json!({
+            "$schema": "./.alphacentauri.config.schema.json",
+            "shell": {
+                "program": "fish",
+                "args": [],
+                "env": {},
+                "bell": true,
+                "fonts": "Consolas, Monospace",
+                "changeDirectoryOscCode": 7,
+                "changeWindowTitleOscCode": if cfg!(target_os = "windows") { 0 } else { 2 }
+            },
+            "keymaps": {"edit:copy": "ctrl+shift+c", "edit:paste": "ctrl+v"},
+            "profiles": {},
+            "defaultProfile": null
+        });
+
+        let changes = migrate(&mut value).unwrap();
+
+        // the default fonts were removed, so moving them changed nothing
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].starts_with("Version 1 to 2"));
+        assert_eq!(
+            value,
+            json!({
+                "$schema": "./.alphacentauri.config.schema.json",
+                "shell": {"program": "fish"},
+                "keymaps": {"edit:paste": "ctrl+v"},
+                "version": CURRENT_CONFIG_VERSION
+            })
+        );
+        assert!(migrate(&mut value).unwrap().is_empty());
+    }
+
+    #[test]
+    fn migrate_keeps_defaults_written_by_hand() {
+        let mut value = json!({"shell": {"bell": true, "program": "fish"}});
+
+        let changes = migrate(&mut value).unwrap();
+
+        assert!(changes.is_empty());
+        assert_eq!(value["shell"], json!({"bell": true, "program": "fish"}));
+    }
+
+    #[test]
+    fn migrate_moves_version_2_fonts_to_font_section() {
+        let mut value = json!({
+            "version": 2,
+            "shell": {"program": "fish", "fonts": "Fira Code, 'DejaVu Sans Mono', monospace"},
+            "profiles": {"ops": {"fonts": "Hack"}, "dev": {"program": "zsh"}}
+        });
+
+        let changes = migrate(&mut value).unwrap();
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            value,
+            json!({
+                "version": CURRENT_CONFIG_VERSION,
+                "shell": {"program": "fish"},
+                "font": {"family": ["Fira Code", "DejaVu Sans Mono", "monospace"]},
+                "profiles": {"ops": {"font": {"family": ["Hack"]}}, "dev": {"program": "zsh"}}
+            })
+        );
+    }
+
+    #[test]
+    fn migrate_rejects_newer_versions() {
+        let mut value = json!({"version": CURRENT_CONFIG_VERSION + 1, "shell": {"bell": true}});
+
+        assert!(migrate(&mut value).is_err());
+        assert_eq!(value["shell"]["bell"], json!(true));
+    }
+}
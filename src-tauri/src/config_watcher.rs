@@ -0,0 +1,109 @@
+use notify_debouncer_mini::{
+    new_debouncer,
+    notify::{RecommendedWatcher, RecursiveMode},
+    DebounceEventResult, DebouncedEvent, Debouncer,
+};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+// editors often write a file several times in quick succession when saving
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(300);
+
+pub type ConfigWatcher = Debouncer<RecommendedWatcher>;
+
+// Other files in the watched directory change too
+fn changes_file(events: &[DebouncedEvent], file_name: Option<&OsStr>) -> bool {
+    events
+        .iter()
+        .any(|event| event.path.file_name() == file_name)
+}
+
+// Watches the directory containing the file rather than the file itself, so saves that
+// replace the file (write to a temp file, then rename) are still picked up
+pub fn watch_file<F>(
+    file_loc: &Path,
+    on_change: F,
+) -> notify_debouncer_mini::notify::Result<ConfigWatcher>
+where
+    F: Fn() + Send + 'static,
+{
+    let watched_file = std::fs::canonicalize(file_loc).unwrap_or(file_loc.to_path_buf());
+    let file_name = watched_file.file_name().map(|name| name.to_os_string());
+    let directory = watched_file
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or(PathBuf::from("."));
+
+    let mut debouncer = new_debouncer(DEBOUNCE_TIMEOUT, move |result: DebounceEventResult| {
+        match result {
+            Ok(events) => {
+                // a missing file is most likely mid-save, the next event will pick it up
+                if changes_file(&events, file_name.as_deref()) && watched_file.exists() {
+                    on_change();
+                }
+            }
+            Err(_e) => {
+                #[cfg(debug_assertions)]
+                println!("Error watching configuration file: {:?}", _e);
+            }
+        }
+    })?;
+    debouncer
+        .watcher()
+        .watch(&directory, RecursiveMode::NonRecursive)?;
+
+    Ok(debouncer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify_debouncer_mini::DebouncedEventKind;
+    use std::sync::mpsc;
+    use tempdir::TempDir;
+
+    // generous for slow CI machines, receiving returns as soon as the change is reported
+    const DEADLINE: Duration = Duration::from_secs(30);
+
+    #[test]
+    fn changes_file_ignores_other_files() {
+        let event = |path: &str| DebouncedEvent {
+            path: PathBuf::from(path),
+            kind: DebouncedEventKind::Any,
+        };
+        let file_name = Some(OsStr::new("userConfig.json"));
+
+        assert!(!changes_file(
+            &[event("/home/user/unrelated.json")],
+            file_name
+        ));
+        assert!(changes_file(
+            &[
+                event("/home/user/unrelated.json"),
+                event("/home/user/userConfig.json")
+            ],
+            file_name
+        ));
+    }
+
+    #[test]
+    fn watch_file_reports_changes_to_the_file() {
+        let dir = TempDir::new("usr_home").unwrap();
+        let file_path = dir.path().join("userConfig.json");
+        std::fs::write(&file_path, "{}").unwrap();
+        let (sender, receiver) = mpsc::channel();
+
+        let _watcher = watch_file(&file_path, move || {
+            let _ = sender.send(());
+        })
+        .unwrap();
+
+        std::fs::write(&file_path, "{\"changed\":true}").unwrap();
+        assert!(receiver.recv_timeout(DEADLINE).is_ok());
+
+        let _ = dir.close();
+    }
+}
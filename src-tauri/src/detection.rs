@@ -0,0 +1,319 @@
+use regex::Regex;
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use dir::home_dir;
+
+// how much raw output each session keeps around for link detection
+const MAX_RETAINED_OUTPUT: usize = 1024 * 1024;
+// each path candidate is looked up on disk, output with more of them is only partly checked
+const MAX_PATH_CANDIDATES: usize = 200;
+
+// Raw session output, addressed by the same stream offsets the OSC parser reports
+#[derive(Debug, Default)]
+pub struct OutputBuffer {
+    data: VecDeque<u8>,
+    start_offset: u64,
+}
+
+impl OutputBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.data.extend(bytes);
+        let overflow = self.data.len().saturating_sub(MAX_RETAINED_OUTPUT);
+        if overflow > 0 {
+            self.data.drain(..overflow);
+            self.start_offset += overflow as u64;
+        }
+    }
+
+    pub fn end_offset(&self) -> u64 {
+        self.start_offset + self.data.len() as u64
+    }
+
+    // Offset of the start of the last `lines` lines, a trailing newline does not start a line
+    pub fn last_lines_start(&self, lines: usize) -> u64 {
+        let mut newlines = 0;
+        let end = self.data.len().saturating_sub(1);
+        for (index, byte) in self.data.range(..end).enumerate().rev() {
+            if *byte == b'\n' {
+                newlines += 1;
+                if newlines == lines {
+                    return self.start_offset + index as u64 + 1;
+                }
+            }
+        }
+        self.start_offset
+    }
+
+    // ranges that reach outside the retained output are clamped to it
+    pub fn range(&self, start: u64, end: u64) -> Vec<u8> {
+        let start = start.max(self.start_offset);
+        let end = end.min(self.end_offset());
+        if start >= end {
+            return Vec::new();
+        }
+        let from = (start - self.start_offset) as usize;
+        let to = (end - self.start_offset) as usize;
+        self.data.range(from..to).copied().collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum DetectedLinkKind {
+    Url,
+    Path,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DetectedLink {
+    pub kind: DetectedLinkKind,
+    // the text as it appeared in the output
+    pub text: String,
+    // url, or the absolute path on this machine
+    pub target: String,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    // character offsets into the output text once escape sequences are removed
+    pub start: usize,
+    pub end: usize,
+}
+
+fn url_regex() -> &'static Regex {
+    static URL_REGEX: OnceLock<Regex> = OnceLock::new();
+    URL_REGEX.get_or_init(|| {
+        Regex::new(r#"\b(?:https?|ftp|file)://[^\s<>"'`]*[^\s<>"'`.,;:!?)\]}]"#).unwrap()
+    })
+}
+
+fn path_regex() -> &'static Regex {
+    static PATH_REGEX: OnceLock<Regex> = OnceLock::new();
+    PATH_REGEX.get_or_init(|| {
+        Regex::new(
+            r"(?P<path>(?:[A-Za-z]:[\\/]|~[\\/]|\.{1,2}[\\/]|[\\/])?(?:[\w.\-@+]+[\\/])*[\w\-@+][\w.\-@+]*)(?::(?P<line>\d+))?(?::(?P<column>\d+))?",
+        )
+        .unwrap()
+    })
+}
+
+// Removes CSI, OSC and other escape sequences and control characters, keeping newlines and tabs
+pub fn strip_escape_sequences(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                Some('[') => {
+                    for next in chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&next) {
+                            break;
+                        }
+                    }
+                }
+                Some(']') | Some('P') | Some('_') | Some('^') => {
+                    while let Some(next) = chars.next() {
+                        if next == '\x07' {
+                            break;
+                        }
+                        if next == '\x1b' && chars.peek() == Some(&'\\') {
+                            chars.next();
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\n' | '\t' => result.push(c),
+            '\r' => {
+                if chars.peek() != Some(&'\n') {
+                    result.push('\n');
+                }
+            }
+            c if c.is_control() => {}
+            c => result.push(c),
+        }
+    }
+
+    result
+}
+
+// Plain words are not looked up, a path needs a directory or a file extension
+fn looks_like_path(raw: &str) -> bool {
+    if raw.contains(['/', '\\']) {
+        return true;
+    }
+    match raw.rsplit_once('.') {
+        Some((name, extension)) => {
+            !name.is_empty()
+                && extension.len() <= 10
+                && extension.chars().all(|c| c.is_ascii_alphanumeric())
+                && extension.chars().any(|c| c.is_ascii_alphabetic())
+        }
+        None => false,
+    }
+}
+
+fn resolve_path(raw: &str, cwd: Option<&Path>) -> Option<PathBuf> {
+    let path = if let Some(rest) = raw.strip_prefix("~/").or_else(|| raw.strip_prefix("~\\")) {
+        home_dir()?.join(rest)
+    } else {
+        let path = PathBuf::from(raw);
+        if path.is_absolute() {
+            path
+        } else {
+            cwd?.join(path)
+        }
+    };
+    if std::fs::metadata(&path).is_ok() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+pub fn detect_links(text: &str, cwd: Option<&Path>) -> Vec<DetectedLink> {
+    let mut links: Vec<DetectedLink> = url_regex()
+        .find_iter(text)
+        .map(|m| DetectedLink {
+            kind: DetectedLinkKind::Url,
+            text: m.as_str().to_string(),
+            target: m.as_str().to_string(),
+            line: None,
+            column: None,
+            start: m.start(),
+            end: m.end(),
+        })
+        .collect();
+    let url_ranges: Vec<(usize, usize)> = links.iter().map(|l| (l.start, l.end)).collect();
+
+    let candidates = path_regex()
+        .captures_iter(text)
+        .filter(|captures| {
+            captures.get(0).is_some_and(|whole| {
+                !url_ranges
+                    .iter()
+                    .any(|(start, end)| whole.start() < *end && whole.end() > *start)
+            })
+        })
+        .filter(|captures| {
+            captures
+                .name("path")
+                .is_some_and(|path| looks_like_path(path.as_str()))
+        })
+        .take(MAX_PATH_CANDIDATES);
+    for captures in candidates {
+        let (Some(whole), Some(path)) = (captures.get(0), captures.name("path")) else {
+            continue;
+        };
+        if let Some(resolved) = resolve_path(path.as_str(), cwd) {
+            links.push(DetectedLink {
+                kind: DetectedLinkKind::Path,
+                text: whole.as_str().to_string(),
+                target: resolved.to_string_lossy().to_string(),
+                line: captures.name("line").and_then(|l| l.as_str().parse().ok()),
+                column: captures
+                    .name("column")
+                    .and_then(|c| c.as_str().parse().ok()),
+                start: whole.start(),
+                end: whole.end(),
+            });
+        }
+    }
+
+    links.sort_by_key(|link| link.start);
+    // regex offsets are in bytes, the UI works in characters
+    for link in links.iter_mut() {
+        link.start = text[..link.start].chars().count();
+        link.end = link.start + link.text.chars().count();
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn output_buffer_keeps_stream_offsets() {
+        let mut buffer = OutputBuffer::new();
+        buffer.push(b"hello ");
+        buffer.push(b"world");
+
+        assert_eq!(buffer.end_offset(), 11);
+        assert_eq!(buffer.range(6, 11), b"world".to_vec());
+        assert_eq!(buffer.range(8, 100), b"rld".to_vec());
+    }
+
+    #[test]
+    fn output_buffer_finds_the_start_of_the_last_lines() {
+        let mut buffer = OutputBuffer::new();
+        buffer.push(b"one\ntwo\nthree\n");
+
+        assert_eq!(buffer.last_lines_start(1), 8);
+        assert_eq!(buffer.last_lines_start(2), 4);
+        assert_eq!(buffer.last_lines_start(10), 0);
+    }
+
+    #[test]
+    fn strip_escape_sequences_removes_csi_and_osc() {
+        let actual = strip_escape_sequences(
+            b"\x1b[1;32mok\x1b[0m \x1b]8;;https://a.b\x07link\x1b]8;;\x1b\\\r\n",
+        );
+
+        assert_eq!(actual, "ok link\n");
+    }
+
+    #[test]
+    fn detect_links_finds_urls_and_existing_paths() {
+        let dir = TempDir::new("usr_project").unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src").join("main.rs"), "fn main() {}").unwrap();
+        let text = "error at src/main.rs:12:5 see https://example.com/docs. missing.rs:1";
+
+        let actual = detect_links(text, Some(dir.path()));
+
+        assert_eq!(actual.len(), 2);
+        assert_eq!(actual[0].kind, DetectedLinkKind::Path);
+        assert_eq!(actual[0].text, "src/main.rs:12:5");
+        assert_eq!(
+            PathBuf::from(&actual[0].target),
+            dir.path().join("src/main.rs")
+        );
+        assert_eq!(actual[0].line, Some(12));
+        assert_eq!(actual[0].column, Some(5));
+        assert_eq!(actual[0].start, 9);
+        assert_eq!(actual[1].kind, DetectedLinkKind::Url);
+        assert_eq!(actual[1].target, "https://example.com/docs");
+
+        let _ = dir.close();
+    }
+
+    #[test]
+    fn detect_links_ignores_plain_words() {
+        let dir = TempDir::new("usr_project").unwrap();
+        std::fs::write(dir.path().join("Makefile"), "all:").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "").unwrap();
+
+        let actual = detect_links("run Makefile then notes.txt", Some(dir.path()));
+
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual[0].text, "notes.txt");
+        assert!(!looks_like_path("1.2"));
+        assert!(looks_like_path("./Makefile"));
+        assert!(looks_like_path("~/bin"));
+
+        let _ = dir.close();
+    }
+}
@@ -0,0 +1,296 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, process::Command};
+
+// CSS generic families always resolve to some installed font
+const GENERIC_FAMILIES: &[&str] = &[
+    "monospace",
+    "ui-monospace",
+    "serif",
+    "sans-serif",
+    "system-ui",
+    "cursive",
+    "fantasy",
+    "emoji",
+    "math",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Font {
+    // tried in order, e.g. ["JetBrains Mono", "Consolas", "monospace"]
+    pub family: Vec<String>,
+    // in pixels
+    pub size: f64,
+    // a multiple of the font's height, at least 1
+    pub line_height: f64,
+    // "normal", "bold" or 100 to 900
+    pub weight: String,
+    // of bold text
+    pub bold_weight: String,
+    // joins e.g. "->" into one glyph in fonts such as Fira Code
+    pub ligatures: bool,
+    // Fonts for bold, italic and bold italic text by the name of the face, e.g.
+    // "Iosevka Bold Italic". Left empty the matching face of the regular family is used
+    pub bold_family: Vec<String>,
+    pub italic_family: Vec<String>,
+    pub bold_italic_family: Vec<String>,
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        #[cfg(target_os = "windows")]
+        let family = Vec::from([String::from("Consolas"), String::from("monospace")]);
+        #[cfg(target_os = "macos")]
+        let family = Vec::from([String::from("Menlo"), String::from("monospace")]);
+        // fontconfig picks the system's monospace font
+        #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+        let family = Vec::from([String::from("monospace")]);
+
+        Font {
+            family,
+            size: 15.0,
+            line_height: 1.0,
+            weight: String::from("normal"),
+            bold_weight: String::from("bold"),
+            ligatures: false,
+            bold_family: Vec::new(),
+            italic_family: Vec::new(),
+            bold_italic_family: Vec::new(),
+        }
+    }
+}
+
+// A profile's font settings, anything left unset comes from the font section
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FontOverride {
+    pub family: Option<Vec<String>>,
+    pub size: Option<f64>,
+    pub line_height: Option<f64>,
+    pub weight: Option<String>,
+    pub bold_weight: Option<String>,
+    pub ligatures: Option<bool>,
+    pub bold_family: Option<Vec<String>>,
+    pub italic_family: Option<Vec<String>>,
+    pub bold_italic_family: Option<Vec<String>>,
+}
+
+impl FontOverride {
+    pub fn apply(&self, font: &Font) -> Font {
+        let font = font.clone();
+        Font {
+            family: self.family.clone().unwrap_or(font.family),
+            size: self.size.unwrap_or(font.size),
+            line_height: self.line_height.unwrap_or(font.line_height),
+            weight: self.weight.clone().unwrap_or(font.weight),
+            bold_weight: self.bold_weight.clone().unwrap_or(font.bold_weight),
+            ligatures: self.ligatures.unwrap_or(font.ligatures),
+            bold_family: self.bold_family.clone().unwrap_or(font.bold_family),
+            italic_family: self.italic_family.clone().unwrap_or(font.italic_family),
+            bold_italic_family: self
+                .bold_italic_family
+                .clone()
+                .unwrap_or(font.bold_italic_family),
+        }
+    }
+}
+
+fn valid_weight(weight: &str) -> bool {
+    match weight {
+        "normal" | "bold" => true,
+        _ => weight
+            .parse::<u32>()
+            .is_ok_and(|weight| (100..=900).contains(&weight) && weight % 100 == 0),
+    }
+}
+
+// Splits a line of `fc-list : family` output into the family's names, the first is the
+// canonical one and the rest are localized. fc-list escapes commas, dashes and colons in names
+pub fn parse_fc_list_line(line: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut name = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => name.extend(chars.next()),
+            ',' => names.push(std::mem::take(&mut name)),
+            _ => name.push(c),
+        }
+    }
+    names.push(name);
+    names
+        .into_iter()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+fn fc_list(pattern: &str, element: &str) -> Result<Vec<Vec<String>>, String> {
+    let output = Command::new("fc-list")
+        .args([pattern, element])
+        .output()
+        .map_err(|e| format!("fc-list could not be run: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "fc-list failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(parse_fc_list_line)
+        .filter(|names| !names.is_empty())
+        .collect())
+}
+
+// Installed fixed width families through fontconfig, sorted by name. Fonts with a few double
+// width glyphs, such as Iosevka, are dual spaced rather than mono
+pub fn list_monospace_fonts() -> Result<Vec<String>, String> {
+    let mut families: Vec<String> = fc_list(":spacing=mono", "family")?
+        .into_iter()
+        .chain(fc_list(":spacing=dual", "family")?)
+        .filter_map(|names| names.into_iter().next())
+        .collect();
+    families.sort_by_key(|family| family.to_lowercase());
+    families.dedup();
+    Ok(families)
+}
+
+// Every name of every installed family and face in lower case, or None without fontconfig
+// (e.g. on Windows and macOS) since then nothing can be said about which fonts are missing
+pub fn installed_font_names() -> Option<HashSet<String>> {
+    let families = fc_list(":", "family").ok()?;
+    let faces = fc_list(":", "fullname").ok()?;
+    Some(
+        families
+            .into_iter()
+            .chain(faces)
+            .flatten()
+            .map(|name| name.to_lowercase())
+            .collect(),
+    )
+}
+
+// Settings that would make the terminal fall back to other fonts without saying so
+pub fn font_problems(font: &Font, installed: Option<&HashSet<String>>) -> Vec<String> {
+    let mut problems = Vec::new();
+    if font.family.iter().all(|family| family.trim().is_empty()) {
+        problems.push(String::from("font.family: no font family is set"));
+    }
+    if font.size <= 0.0 {
+        problems.push(format!("font.size: {} is not a valid size", font.size));
+    }
+    if font.line_height < 1.0 {
+        problems.push(format!(
+            "font.lineHeight: {} is less than 1",
+            font.line_height
+        ));
+    }
+
+    for (path, weight) in [("weight", &font.weight), ("boldWeight", &font.bold_weight)] {
+        if !valid_weight(weight) {
+            problems.push(format!(
+                "font.{}: {:?} is not \"normal\", \"bold\" or 100 to 900",
+                path, weight
+            ));
+        }
+    }
+    let Some(installed) = installed else {
+        return problems;
+    };
+    let styles = [
+        ("family", &font.family),
+        ("boldFamily", &font.bold_family),
+        ("italicFamily", &font.italic_family),
+        ("boldItalicFamily", &font.bold_italic_family),
+    ];
+    for (path, families) in styles {
+        for family in families {
+            let name = family.trim().to_lowercase();
+            if !name.is_empty()
+                && !GENERIC_FAMILIES.contains(&name.as_str())
+                && !installed.contains(&name)
+            {
+                problems.push(format!("font.{}: {:?} is not installed", path, family));
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_fc_list_line_splits_localized_names() {
+        assert_eq!(
+            parse_fc_list_line("DejaVu Sans Mono,DejaVu Sans Mono Book"),
+            Vec::from([
+                String::from("DejaVu Sans Mono"),
+                String::from("DejaVu Sans Mono Book")
+            ])
+        );
+        assert_eq!(
+            parse_fc_list_line("Fira Code\\,Retina\\-Light"),
+            Vec::from([String::from("Fira Code,Retina-Light")])
+        );
+        assert!(parse_fc_list_line("  ").is_empty());
+    }
+
+    #[test]
+    fn font_problems_reports_missing_families_and_bad_values() {
+        let installed = HashSet::from(["fira code", "consolas", "menlo"].map(String::from));
+        let font = Font {
+            family: Vec::from([
+                String::from("Fira Code"),
+                String::from("Missing Mono"),
+                String::from("monospace"),
+            ]),
+            line_height: 0.5,
+            weight: String::from("450"),
+            bold_weight: String::from("heavy"),
+            italic_family: Vec::from([String::from("Missing Italic")]),
+            ..Font::default()
+        };
+
+        let actual = font_problems(&font, Some(&installed));
+
+        assert_eq!(
+            actual,
+            Vec::from([
+                String::from("font.lineHeight: 0.5 is less than 1"),
+                String::from("font.weight: \"450\" is not \"normal\", \"bold\" or 100 to 900"),
+                String::from(
+                    "font.boldWeight: \"heavy\" is not \"normal\", \"bold\" or 100 to 900"
+                ),
+                String::from("font.family: \"Missing Mono\" is not installed"),
+                String::from("font.italicFamily: \"Missing Italic\" is not installed"),
+            ])
+        );
+        assert!(font_problems(&Font::default(), Some(&installed)).is_empty());
+        assert_eq!(font_problems(&font, None).len(), 3);
+    }
+
+    #[test]
+    fn font_override_replaces_only_what_it_sets() {
+        let font = Font {
+            family: Vec::from([String::from("Fira Code")]),
+            ligatures: true,
+            ..Font::default()
+        };
+        let font_override = FontOverride {
+            family: Some(Vec::from([String::from("Hack")])),
+            size: Some(18.0),
+            ..FontOverride::default()
+        };
+
+        let actual = font_override.apply(&font);
+
+        assert_eq!(actual.family, Vec::from([String::from("Hack")]));
+        assert_eq!(actual.size, 18.0);
+        assert_eq!(actual.line_height, font.line_height);
+        assert!(actual.ligatures);
+    }
+}
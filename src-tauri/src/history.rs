@@ -0,0 +1,275 @@
+use rusqlite::{params, params_from_iter, types::Value, Connection};
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::Path};
+
+const DEFAULT_SEARCH_LIMIT: u32 = 100;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub command: String,
+    pub cwd: Option<String>,
+    pub host: String,
+    pub exit_code: Option<i32>,
+    // milliseconds since the unix epoch
+    pub started_at: i64,
+    pub duration_ms: i64,
+    pub session_id: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewHistoryEntry {
+    pub command: String,
+    pub cwd: Option<String>,
+    pub host: String,
+    pub exit_code: Option<i32>,
+    pub started_at: i64,
+    pub duration_ms: i64,
+    pub session_id: u32,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryFilter {
+    // substring match on the command text
+    pub query: Option<String>,
+    pub cwd: Option<String>,
+    pub host: Option<String>,
+    pub exit_code: Option<i32>,
+    pub succeeded: Option<bool>,
+    pub session_id: Option<u32>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HistoryError {
+    Open(String),
+    Write(String),
+    Read(String),
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            HistoryError::Open(e) => write!(f, "<div class='friendly-error-message'>Unable to open the command history database.</div><div class='raw-error ml-4 mt-2'>{}</div>", e),
+            HistoryError::Write(e) => write!(f, "<div class='friendly-error-message'>Unable to save to the command history database.</div><div class='raw-error ml-4 mt-2'>{}</div>", e),
+            HistoryError::Read(e) => write!(f, "<div class='friendly-error-message'>Unable to search the command history database.</div><div class='raw-error ml-4 mt-2'>{}</div>", e),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, HistoryError>;
+
+pub struct CommandHistory {
+    connection: Connection,
+}
+
+impl CommandHistory {
+    pub fn open(file_loc: &Path) -> Result<Self> {
+        if let Some(parent) = file_loc.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| HistoryError::Open(format!("{:?}", e)))?;
+        }
+        let connection =
+            Connection::open(file_loc).map_err(|e| HistoryError::Open(format!("{:?}", e)))?;
+        Self::initialize(connection)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self> {
+        let connection =
+            Connection::open_in_memory().map_err(|e| HistoryError::Open(format!("{:?}", e)))?;
+        Self::initialize(connection)
+    }
+
+    fn initialize(connection: Connection) -> Result<Self> {
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS history (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    command TEXT NOT NULL,
+                    cwd TEXT,
+                    host TEXT NOT NULL,
+                    exit_code INTEGER,
+                    started_at INTEGER NOT NULL,
+                    duration_ms INTEGER NOT NULL,
+                    session_id INTEGER NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS history_started_at ON history (started_at);
+                CREATE INDEX IF NOT EXISTS history_cwd ON history (cwd);",
+            )
+            .map_err(|e| HistoryError::Open(format!("{:?}", e)))?;
+        Ok(CommandHistory { connection })
+    }
+
+    pub fn record(&self, entry: &NewHistoryEntry) -> Result<i64> {
+        self.connection
+            .execute(
+                "INSERT INTO history (command, cwd, host, exit_code, started_at, duration_ms, session_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    entry.command,
+                    entry.cwd,
+                    entry.host,
+                    entry.exit_code,
+                    entry.started_at,
+                    entry.duration_ms,
+                    entry.session_id
+                ],
+            )
+            .map_err(|e| HistoryError::Write(format!("{:?}", e)))?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    pub fn search(&self, filter: &HistoryFilter) -> Result<Vec<HistoryEntry>> {
+        let mut conditions: Vec<&str> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        if let Some(query) = &filter.query {
+            conditions.push("instr(command, ?) > 0");
+            values.push(Value::Text(query.clone()));
+        }
+        if let Some(cwd) = &filter.cwd {
+            conditions.push("cwd = ?");
+            values.push(Value::Text(cwd.clone()));
+        }
+        if let Some(host) = &filter.host {
+            conditions.push("host = ?");
+            values.push(Value::Text(host.clone()));
+        }
+        if let Some(exit_code) = filter.exit_code {
+            conditions.push("exit_code = ?");
+            values.push(Value::Integer(i64::from(exit_code)));
+        }
+        match filter.succeeded {
+            Some(true) => conditions.push("exit_code = 0"),
+            Some(false) => conditions.push("(exit_code IS NULL OR exit_code != 0)"),
+            None => {}
+        }
+        if let Some(session_id) = filter.session_id {
+            conditions.push("session_id = ?");
+            values.push(Value::Integer(i64::from(session_id)));
+        }
+        if let Some(since) = filter.since {
+            conditions.push("started_at >= ?");
+            values.push(Value::Integer(since));
+        }
+        if let Some(until) = filter.until {
+            conditions.push("started_at <= ?");
+            values.push(Value::Integer(until));
+        }
+
+        let mut sql = String::from(
+            "SELECT id, command, cwd, host, exit_code, started_at, duration_ms, session_id FROM history",
+        );
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(" ORDER BY started_at DESC, id DESC LIMIT ?");
+        values.push(Value::Integer(i64::from(
+            filter.limit.unwrap_or(DEFAULT_SEARCH_LIMIT),
+        )));
+
+        let mut statement = self
+            .connection
+            .prepare(&sql)
+            .map_err(|e| HistoryError::Read(format!("{:?}", e)))?;
+        let rows = statement
+            .query_map(params_from_iter(values), |row| {
+                Ok(HistoryEntry {
+                    id: row.get(0)?,
+                    command: row.get(1)?,
+                    cwd: row.get(2)?,
+                    host: row.get(3)?,
+                    exit_code: row.get(4)?,
+                    started_at: row.get(5)?,
+                    duration_ms: row.get(6)?,
+                    session_id: row.get(7)?,
+                })
+            })
+            .map_err(|e| HistoryError::Read(format!("{:?}", e)))?;
+
+        rows.collect::<std::result::Result<Vec<HistoryEntry>, _>>()
+            .map_err(|e| HistoryError::Read(format!("{:?}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn entry(command: &str, cwd: &str, exit_code: i32, started_at: i64) -> NewHistoryEntry {
+        NewHistoryEntry {
+            command: command.to_string(),
+            cwd: Some(cwd.to_string()),
+            host: "machine".to_string(),
+            exit_code: Some(exit_code),
+            started_at,
+            duration_ms: 10,
+            session_id: 42,
+        }
+    }
+
+    #[test]
+    fn open_creates_database_file() {
+        let dir = TempDir::new("usr_state").unwrap();
+        let file_path = dir.path().join("nested").join("history.sqlite3");
+
+        let history = CommandHistory::open(&file_path);
+
+        assert!(history.is_ok());
+        assert!(file_path.exists());
+
+        let _ = dir.close();
+    }
+
+    #[test]
+    fn search_applies_filters() {
+        let history = CommandHistory::open_in_memory().unwrap();
+        history
+            .record(&entry("cargo build", "/src/app", 0, 1000))
+            .unwrap();
+        history
+            .record(&entry("cargo test", "/src/app", 101, 2000))
+            .unwrap();
+        history.record(&entry("ls", "/home", 0, 3000)).unwrap();
+
+        let in_cwd = history
+            .search(&HistoryFilter {
+                cwd: Some("/src/app".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            in_cwd
+                .iter()
+                .map(|e| e.command.as_str())
+                .collect::<Vec<_>>(),
+            Vec::from(["cargo test", "cargo build"])
+        );
+
+        let failed = history
+            .search(&HistoryFilter {
+                succeeded: Some(false),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].exit_code, Some(101));
+
+        let in_range = history
+            .search(&HistoryFilter {
+                query: Some("cargo".to_string()),
+                since: Some(1500),
+                until: Some(3500),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(in_range.len(), 1);
+        assert_eq!(in_range[0].command, "cargo test");
+    }
+}
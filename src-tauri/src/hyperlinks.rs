@@ -0,0 +1,146 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use url::Url;
+
+use crate::osc::OscSequence;
+
+// OSC 8: `ESC ] 8 ; params ; URI ST` opens a link, an empty URI closes it
+pub const HYPERLINK_OSC_CODE: &str = "8";
+
+// oldest links are dropped once a session has emitted this many
+const MAX_TRACKED_HYPERLINKS: usize = 2000;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Hyperlink {
+    // assigned by the tracker, used to ask the backend to open the link
+    pub index: u64,
+    // the explicit `id=` parameter, links sharing an id and uri are the same link
+    pub link_id: Option<String>,
+    pub uri: String,
+    // byte offsets into the session output stream covered by the link text
+    pub start: u64,
+    pub end: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct HyperlinkTracker {
+    links: VecDeque<Hyperlink>,
+    next_index: u64,
+    open: bool,
+}
+
+impl HyperlinkTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn handle(&mut self, sequence: &OscSequence) {
+        if sequence.code != HYPERLINK_OSC_CODE {
+            return;
+        }
+        let (params, uri) = sequence.data.split_once(';').unwrap_or(("", ""));
+
+        // any new OSC 8 implicitly ends the previous link
+        self.close(sequence.start);
+        if uri.is_empty() {
+            return;
+        }
+
+        let link_id = params
+            .split(':')
+            .find_map(|param| param.strip_prefix("id="))
+            .map(String::from);
+        self.links.push_back(Hyperlink {
+            index: self.next_index,
+            link_id,
+            uri: uri.to_string(),
+            start: sequence.end,
+            end: None,
+        });
+        self.next_index += 1;
+        self.open = true;
+
+        if self.links.len() > MAX_TRACKED_HYPERLINKS {
+            self.links.pop_front();
+        }
+    }
+
+    fn close(&mut self, offset: u64) {
+        if self.open {
+            if let Some(link) = self.links.back_mut() {
+                link.end = Some(offset);
+            }
+            self.open = false;
+        }
+    }
+
+    pub fn links(&self) -> Vec<Hyperlink> {
+        self.links.iter().cloned().collect()
+    }
+
+    pub fn get(&self, index: u64) -> Option<&Hyperlink> {
+        self.links.iter().find(|link| link.index == index)
+    }
+}
+
+pub fn is_scheme_allowed(uri: &str, allowed_schemes: &[String]) -> bool {
+    match Url::parse(uri) {
+        Ok(url) => allowed_schemes
+            .iter()
+            .any(|scheme| scheme.eq_ignore_ascii_case(url.scheme())),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence(data: &str, start: u64, end: u64) -> OscSequence {
+        OscSequence {
+            code: "8".to_string(),
+            data: data.to_string(),
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn handle_tracks_link_ranges_and_ids() {
+        let mut tracker = HyperlinkTracker::new();
+
+        tracker.handle(&sequence("id=abc;https://example.com", 0, 32));
+        tracker.handle(&sequence(";", 40, 46));
+        tracker.handle(&sequence(";file:///etc/hosts", 50, 74));
+
+        assert_eq!(
+            tracker.links(),
+            Vec::from([
+                Hyperlink {
+                    index: 0,
+                    link_id: Some("abc".to_string()),
+                    uri: "https://example.com".to_string(),
+                    start: 32,
+                    end: Some(40),
+                },
+                Hyperlink {
+                    index: 1,
+                    link_id: None,
+                    uri: "file:///etc/hosts".to_string(),
+                    start: 74,
+                    end: None,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn is_scheme_allowed_checks_allowlist() {
+        let allowed = Vec::from(["http".to_string(), "https".to_string()]);
+
+        assert!(is_scheme_allowed("https://example.com", &allowed));
+        assert!(!is_scheme_allowed("ms-settings:display", &allowed));
+        assert!(!is_scheme_allowed("not a url", &allowed));
+    }
+}
@@ -0,0 +1,549 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt};
+
+use crate::command_registry::CommandInfo;
+
+// names accepted for keys that aren't a single character, and the name they normalize to.
+// Apart from Space and Plus, which can't be told apart from the separator, these are
+// KeyboardEvent.key values
+const NAMED_KEYS: &[(&[&str], &str)] = &[
+    (&["arrowup", "up"], "ArrowUp"),
+    (&["arrowdown", "down"], "ArrowDown"),
+    (&["arrowleft", "left"], "ArrowLeft"),
+    (&["arrowright", "right"], "ArrowRight"),
+    (&["enter", "return"], "Enter"),
+    (&["escape", "esc"], "Escape"),
+    (&["tab"], "Tab"),
+    (&["backspace"], "Backspace"),
+    (&["delete", "del"], "Delete"),
+    (&["insert", "ins"], "Insert"),
+    (&["home"], "Home"),
+    (&["end"], "End"),
+    (&["pageup", "pgup"], "PageUp"),
+    (&["pagedown", "pgdn"], "PageDown"),
+    (&["space"], "Space"),
+    (&["plus"], "Plus"),
+];
+
+// One binding in the config, a combo or a combo with arguments for the command. Each combo
+// is a sequence of one or more chords separated by spaces, e.g. "ctrl+a c"
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[serde(untagged)]
+pub enum KeyBindingConfig {
+    Combo(String),
+    WithArgs {
+        keys: String,
+        #[serde(default)]
+        args: HashMap<String, String>,
+    },
+}
+
+impl KeyBindingConfig {
+    pub fn keys(&self) -> &str {
+        match self {
+            KeyBindingConfig::Combo(keys) | KeyBindingConfig::WithArgs { keys, .. } => keys,
+        }
+    }
+}
+
+impl From<&str> for KeyBindingConfig {
+    fn from(combo: &str) -> Self {
+        KeyBindingConfig::Combo(combo.to_string())
+    }
+}
+
+// A command's bindings in the config, one binding or a list of them
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[serde(untagged)]
+pub enum KeyBindings {
+    One(KeyBindingConfig),
+    Many(Vec<KeyBindingConfig>),
+}
+
+impl KeyBindings {
+    // an empty combo or list binds nothing
+    pub fn entries(&self) -> Vec<&KeyBindingConfig> {
+        let entries = match self {
+            KeyBindings::One(binding) => Vec::from([binding]),
+            KeyBindings::Many(bindings) => bindings.iter().collect(),
+        };
+        entries
+            .into_iter()
+            .filter(|binding| !binding.keys().trim().is_empty())
+            .collect()
+    }
+}
+
+impl From<&str> for KeyBindings {
+    fn from(combo: &str) -> Self {
+        KeyBindings::One(KeyBindingConfig::from(combo))
+    }
+}
+
+// Decodes backslash escapes: \n \r \t \e (escape) \a \b \0 \\ \xHH \uHHHH and \u{H...}
+pub fn decode_escapes(text: &str) -> Result<String, String> {
+    let mut decoded = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            decoded.push(c);
+            continue;
+        }
+        let escape = chars
+            .next()
+            .ok_or_else(|| String::from("the text ends with a lone \\"))?;
+        let simple = match escape {
+            'n' => Some('\n'),
+            'r' => Some('\r'),
+            't' => Some('\t'),
+            'e' | 'E' => Some('\x1b'),
+            'a' => Some('\x07'),
+            'b' => Some('\x08'),
+            '0' => Some('\0'),
+            '\\' => Some('\\'),
+            _ => None,
+        };
+        if let Some(simple) = simple {
+            decoded.push(simple);
+            continue;
+        }
+
+        let hex: String = match escape {
+            'x' => chars.by_ref().take(2).collect(),
+            'u' if chars.peek() == Some(&'{') => {
+                chars.next();
+                chars.by_ref().take_while(|c| *c != '}').collect()
+            }
+            'u' => chars.by_ref().take(4).collect(),
+            other => return Err(format!("\\{} is not an escape sequence", other)),
+        };
+        let code = u32::from_str_radix(&hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| format!("\\{}{} is not a valid character code", escape, hex))?;
+        decoded.push(code);
+    }
+    Ok(decoded)
+}
+
+// A key pressed together with modifiers, e.g. ctrl+shift+c
+#[derive(Debug, Serialize, Clone, PartialEq, Eq, Hash)]
+pub struct Chord {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub meta: bool,
+    pub key: String,
+}
+
+fn normalize_key(key: &str) -> Option<String> {
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(' '), None) => return Some(String::from("Space")),
+        (Some('+'), None) => return Some(String::from("Plus")),
+        (Some(c), None) => return Some(c.to_lowercase().to_string()),
+        _ => {}
+    }
+    let lower = key.to_lowercase();
+    if let Some(number) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+        return (1..=24).contains(&number).then(|| format!("F{}", number));
+    }
+    NAMED_KEYS
+        .iter()
+        .find(|(names, _)| names.contains(&lower.as_str()))
+        .map(|(_, name)| name.to_string())
+}
+
+impl Chord {
+    // Modifiers (ctrl, alt, shift, meta and their aliases) in any order, then the key
+    pub fn parse(combo: &str) -> Result<Chord, String> {
+        let combo = combo.trim();
+        // "ctrl++" binds the + key
+        let (modifiers, key) = match combo.strip_suffix("++") {
+            Some(modifiers) => (modifiers, "+"),
+            None if combo == "+" => ("", "+"),
+            None => match combo.rsplit_once('+') {
+                Some((modifiers, key)) => (modifiers, key.trim()),
+                None => ("", combo),
+            },
+        };
+
+        let mut chord = Chord {
+            ctrl: false,
+            alt: false,
+            shift: false,
+            meta: false,
+            key: String::new(),
+        };
+        for modifier in modifiers.split('+').filter(|m| !m.trim().is_empty()) {
+            match modifier.trim().to_lowercase().as_str() {
+                "ctrl" | "control" => chord.ctrl = true,
+                "alt" | "option" => chord.alt = true,
+                "shift" => chord.shift = true,
+                "meta" | "cmd" | "command" | "super" | "win" => chord.meta = true,
+                other => return Err(format!("{:?} is not a modifier", other)),
+            }
+        }
+        if key.is_empty() {
+            return Err(String::from("no key given"));
+        }
+        chord.key = normalize_key(key).ok_or_else(|| format!("{:?} is not a key", key))?;
+        Ok(chord)
+    }
+}
+
+// ctrl+alt+shift+meta+key, modifiers always in this order
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let modifiers = [
+            (self.ctrl, "ctrl"),
+            (self.alt, "alt"),
+            (self.shift, "shift"),
+            (self.meta, "meta"),
+        ];
+        for (_, name) in modifiers.iter().filter(|(pressed, _)| *pressed) {
+            write!(f, "{}+", name)?;
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+// Chords pressed one after another, e.g. ctrl+a then c
+#[derive(Debug, Serialize, Clone, PartialEq, Eq, Hash)]
+pub struct KeySequence(pub Vec<Chord>);
+
+impl KeySequence {
+    pub fn parse(combo: &str) -> Result<KeySequence, String> {
+        let chords = combo
+            .split_whitespace()
+            .map(Chord::parse)
+            .collect::<Result<Vec<Chord>, String>>()?;
+        if chords.is_empty() {
+            return Err(String::from("no key given"));
+        }
+        Ok(KeySequence(chords))
+    }
+
+    // true when typing this sequence would also complete or start the other one
+    fn overlaps(&self, other: &KeySequence) -> bool {
+        self.0.starts_with(&other.0) || other.0.starts_with(&self.0)
+    }
+}
+
+// chords separated by spaces
+impl fmt::Display for KeySequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let chords: Vec<String> = self.0.iter().map(Chord::to_string).collect();
+        write!(f, "{}", chords.join(" "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedBinding {
+    pub sequence: KeySequence,
+    // checked against the command's arguments, with escapes decoded
+    pub args: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ResolvedKeymaps {
+    // sorted by command name, commands left without a binding are skipped
+    pub bindings: Vec<(String, Vec<ResolvedBinding>)>,
+    pub problems: Vec<String>,
+}
+
+fn resolve_args(
+    command: &CommandInfo,
+    binding: &KeyBindingConfig,
+) -> Result<HashMap<String, String>, String> {
+    let args = match binding {
+        KeyBindingConfig::Combo(_) => HashMap::new(),
+        KeyBindingConfig::WithArgs { args, .. } => args.clone(),
+    };
+    if let Some(name) = args
+        .keys()
+        .find(|name| !command.args.iter().any(|arg| arg.name == name.as_str()))
+    {
+        return Err(format!("{} has no {} argument", command.id, name));
+    }
+
+    let mut resolved = HashMap::new();
+    for arg in command.args {
+        match args.get(arg.name) {
+            Some(value) if arg.escapes => {
+                let value = decode_escapes(value).map_err(|e| format!("{}: {}", arg.name, e))?;
+                resolved.insert(arg.name.to_string(), value);
+            }
+            Some(value) => {
+                resolved.insert(arg.name.to_string(), value.clone());
+            }
+            None if arg.required => {
+                return Err(format!("{} needs a {} argument", command.id, arg.name))
+            }
+            None => {}
+        }
+    }
+    Ok(resolved)
+}
+
+// Parses every binding, leaving out (and reporting) malformed combos or arguments, unknown
+// commands and sequences that are the same as, or a prefix of, one already bound. An empty
+// combo or list unbinds a command silently. Bindings left at their defaults are resolved
+// last and quietly give way to the ones the user set
+pub fn resolve_keymaps(
+    keymaps: &HashMap<String, KeyBindings>,
+    defaults: &HashMap<String, KeyBindings>,
+    commands: &[CommandInfo],
+) -> ResolvedKeymaps {
+    let is_default =
+        |command: &String, bindings: &KeyBindings| defaults.get(command) == Some(bindings);
+    let mut entries: Vec<(&String, &KeyBindings)> = keymaps.iter().collect();
+    entries.sort_by_key(|(command, bindings)| (is_default(command, bindings), *command));
+
+    let mut resolved = ResolvedKeymaps::default();
+    for (command, bindings) in entries {
+        let default = is_default(command, bindings);
+        let entries = bindings.entries();
+        if entries.is_empty() {
+            continue;
+        }
+        let Some(command_info) = commands.iter().find(|info| info.id == command.as_str()) else {
+            let combos: Vec<&str> = entries.iter().map(|binding| binding.keys()).collect();
+            resolved.problems.push(format!(
+                "{} is not a command, its bindings {} were ignored",
+                command,
+                combos.join(", ")
+            ));
+            continue;
+        };
+
+        let mut command_bindings: Vec<ResolvedBinding> = Vec::new();
+        for binding in entries {
+            let combo = binding.keys();
+            let parsed = KeySequence::parse(combo)
+                .and_then(|sequence| Ok((sequence, resolve_args(command_info, binding)?)));
+            let (sequence, args) = match parsed {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    resolved
+                        .problems
+                        .push(format!("{} for {} was ignored: {}", combo, command, e));
+                    continue;
+                }
+            };
+            let bound = resolved
+                .bindings
+                .iter()
+                .flat_map(|(other, bindings)| bindings.iter().map(move |b| (other, &b.sequence)))
+                .chain(command_bindings.iter().map(|b| (command, &b.sequence)))
+                .find(|(_, bound)| bound.overlaps(&sequence));
+            match bound {
+                Some((other, _)) if default && other != command => {}
+                Some((other, bound)) if *bound == sequence && other == command => {
+                    resolved.problems.push(format!(
+                        "{} is listed more than once for {}",
+                        sequence, command
+                    ));
+                }
+                Some((other, bound)) if *bound == sequence => {
+                    resolved.problems.push(format!(
+                        "{} is bound to both {} and {}, it was kept for {}",
+                        sequence, other, command, other
+                    ));
+                }
+                Some((other, bound)) => {
+                    resolved.problems.push(format!(
+                        "{} for {} overlaps {} for {}, it was ignored",
+                        sequence, command, bound, other
+                    ));
+                }
+                None => command_bindings.push(ResolvedBinding { sequence, args }),
+            }
+        }
+        if !command_bindings.is_empty() {
+            resolved.bindings.push((command.clone(), command_bindings));
+        }
+    }
+    resolved.bindings.sort_by(|a, b| a.0.cmp(&b.0));
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_registry::COMMANDS;
+
+    #[test]
+    fn parse_normalizes_chords() {
+        let actual = Chord::parse("Shift+CTRL+C").unwrap();
+        assert_eq!(
+            actual,
+            Chord {
+                ctrl: true,
+                alt: false,
+                shift: true,
+                meta: false,
+                key: String::from("c")
+            }
+        );
+        assert_eq!(actual.to_string(), "ctrl+shift+c");
+        assert_eq!(
+            Chord::parse("cmd+left").unwrap().to_string(),
+            "meta+ArrowLeft"
+        );
+        assert_eq!(Chord::parse("ctrl++").unwrap().to_string(), "ctrl+Plus");
+        assert_eq!(Chord::parse("alt+f12").unwrap().to_string(), "alt+F12");
+        assert_eq!(Chord::parse("esc").unwrap().to_string(), "Escape");
+    }
+
+    #[test]
+    fn parse_rejects_malformed_combos() {
+        assert!(Chord::parse("ctrl+shift+").is_err());
+        assert!(Chord::parse("ctrl+hyper+a").is_err());
+        assert!(Chord::parse("a+b").is_err());
+        assert!(Chord::parse("ctrl+f25").is_err());
+        assert!(Chord::parse("ctrl+pageupp").is_err());
+    }
+
+    #[test]
+    fn key_sequence_parses_chords_separated_by_spaces() {
+        let actual = KeySequence::parse(" Ctrl+A  c ").unwrap();
+
+        assert_eq!(actual.0.len(), 2);
+        assert_eq!(actual.to_string(), "ctrl+a c");
+        assert!(KeySequence::parse("ctrl+a ctrl+").is_err());
+        assert!(KeySequence::parse("  ").is_err());
+    }
+
+    #[test]
+    fn resolve_keymaps_reports_problems() {
+        let keymaps = HashMap::from([
+            (String::from("edit:copy"), KeyBindings::from("ctrl+shift+c")),
+            (
+                String::from("edit:paste"),
+                KeyBindings::from("shift+ctrl+c"),
+            ),
+            (
+                String::from("edit:select_all"),
+                KeyBindings::from("ctrl+shift+"),
+            ),
+            (String::from("edit:interrupt"), KeyBindings::from("")),
+            (String::from("edit:undo"), KeyBindings::from("ctrl+z")),
+        ]);
+
+        let actual = resolve_keymaps(&keymaps, &HashMap::new(), COMMANDS);
+
+        assert_eq!(
+            actual.bindings,
+            Vec::from([(
+                String::from("edit:copy"),
+                Vec::from([ResolvedBinding {
+                    sequence: KeySequence::parse("ctrl+shift+c").unwrap(),
+                    args: HashMap::new()
+                }])
+            )])
+        );
+        assert_eq!(actual.problems.len(), 3);
+    }
+
+    #[test]
+    fn resolve_keymaps_accepts_lists_and_sequences() {
+        let keymaps = HashMap::from([
+            (
+                String::from("window:new_tab"),
+                KeyBindings::Many(Vec::from([
+                    KeyBindingConfig::from("ctrl+shift+t"),
+                    KeyBindingConfig::from("ctrl+a c"),
+                    KeyBindingConfig::from("ctrl+a  C"),
+                ])),
+            ),
+            (
+                String::from("window:split_down"),
+                KeyBindings::from("ctrl+a"),
+            ),
+            (
+                String::from("window:split_right"),
+                KeyBindings::from("ctrl+a %"),
+            ),
+        ]);
+
+        let actual = resolve_keymaps(&keymaps, &HashMap::new(), COMMANDS);
+
+        assert_eq!(actual.bindings.len(), 2);
+        assert_eq!(actual.bindings[0].1.len(), 2);
+        assert_eq!(actual.bindings[1].0, "window:split_right");
+        assert_eq!(actual.problems.len(), 2);
+    }
+
+    #[test]
+    fn resolve_keymaps_checks_and_decodes_args() {
+        let send_text = |keys: &str, args: &[(&str, &str)]| KeyBindingConfig::WithArgs {
+            keys: keys.to_string(),
+            args: args
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        };
+        let keymaps = HashMap::from([(
+            String::from("terminal:send_text"),
+            KeyBindings::Many(Vec::from([
+                send_text("ctrl+Backspace", &[("text", "\\x17")]),
+                send_text("ctrl+g s", &[("text", "git status\\n")]),
+                send_text("ctrl+g d", &[("text", "\\q")]),
+                send_text("ctrl+g l", &[("txt", "ls")]),
+                KeyBindingConfig::from("ctrl+g p"),
+            ])),
+        )]);
+
+        let actual = resolve_keymaps(&keymaps, &HashMap::new(), COMMANDS);
+
+        let texts: Vec<&str> = actual.bindings[0]
+            .1
+            .iter()
+            .map(|binding| binding.args["text"].as_str())
+            .collect();
+        assert_eq!(texts, Vec::from(["\x17", "git status\n"]));
+        assert_eq!(actual.problems.len(), 3);
+    }
+
+    #[test]
+    fn resolve_keymaps_lets_user_bindings_take_default_combos() {
+        let mut keymaps = crate::command_registry::default_keymaps();
+        keymaps.insert(
+            String::from("window:new_tab"),
+            KeyBindings::from("ctrl+shift+c"),
+        );
+
+        let actual = resolve_keymaps(
+            &keymaps,
+            &crate::command_registry::default_keymaps(),
+            COMMANDS,
+        );
+
+        let bound_to = |command: &str| {
+            actual
+                .bindings
+                .iter()
+                .find(|(other, _)| other == command)
+                .map(|(_, bindings)| bindings[0].sequence.to_string())
+        };
+        assert_eq!(
+            bound_to("window:new_tab"),
+            Some(String::from("ctrl+shift+c"))
+        );
+        assert_eq!(bound_to("edit:copy"), None);
+        assert!(actual.problems.is_empty());
+    }
+
+    #[test]
+    fn decode_escapes_decodes_control_characters() {
+        assert_eq!(
+            decode_escapes("\\e[1;5D\\t\\x41\\u00e9\\u{1F600}\\\\").unwrap(),
+            "\x1b[1;5D\tA\u{e9}\u{1F600}\\"
+        );
+        assert!(decode_escapes("\\xZZ").is_err());
+        assert!(decode_escapes("\\q").is_err());
+        assert!(decode_escapes("trailing\\").is_err());
+    }
+}
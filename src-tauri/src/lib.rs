@@ -4,7 +4,12 @@
 use std::{
     collections::{BTreeMap, HashMap},
     ffi::OsString,
-    sync::Arc,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, UNIX_EPOCH},
 };
 
 use tauri::{
@@ -12,23 +17,62 @@ use tauri::{
     AppHandle, Emitter, Manager, Runtime,
 };
 use tauri_plugin_cli::CliExt;
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tauri_plugin_notification::NotificationExt;
+use tauri_plugin_shell::ShellExt;
 
-use sysinfo::{Pid, System};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
 
 use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize};
 
 use serde::{Deserialize, Serialize};
 
-use dir::home_dir;
 use url::Url;
 
+mod app_dirs;
+mod clipboard;
+mod command_registry;
+mod config_diagnostics;
+mod config_format;
+mod config_layers;
+mod config_migrations;
+mod config_watcher;
+mod detection;
+mod fonts;
+mod history;
+mod hyperlinks;
+mod keymap;
+mod osc;
+mod project_trust;
+mod shell_env;
+mod shell_integration;
+mod shells;
+mod ssh_conf;
+mod themes;
 mod usr_conf;
 
+use clipboard::ClipboardRequest;
+use shell_integration::{CommandEvent, FinishedCommand};
+use usr_conf::ClipboardPermission;
+
+// kept in the state directory, see app_dirs::BaseDirs::state_dir()
+const HISTORY_FILE_NAME: &str = "history.sqlite3";
+const RECENT_SSH_HOSTS_FILE_NAME: &str = "recent_ssh_hosts.json";
+const WINDOW_STATE_FILE_NAME: &str = "window-state.json";
+const TRUSTED_PROJECTS_FILE_NAME: &str = "trusted_projects.json";
+// an unanswered clipboard prompt is denied after this long
+const CLIPBOARD_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
 struct Session {
     master: Mutex<Box<dyn MasterPty + Send>>,
     child: Mutex<Box<dyn Child + Send + Sync>>,
     writer: Mutex<Box<dyn std::io::Write + Send>>,
     reader: Mutex<Box<dyn std::io::Read + Send>>,
+    osc_parser: Mutex<osc::OscParser>,
+    command_tracker: Mutex<shell_integration::CommandTracker>,
+    cwd: RwLock<Option<osc::SessionCwd>>,
+    hyperlinks: Mutex<hyperlinks::HyperlinkTracker>,
+    output: Mutex<detection::OutputBuffer>,
 }
 
 #[derive(Deserialize)]
@@ -59,11 +103,65 @@ struct ShellStatus {
     exit_code: Option<u32>,
 }
 
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ClipboardPermissionRequest {
+    request_id: u32,
+    session_id: PtyHandler,
+    // "read" or "write"
+    access: String,
+    text: Option<String>,
+}
+
+// Asks before rewriting the user's file in the current version's format, which drops its
+// comments and formatting
+#[derive(Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct ConfigUpgradeRequest {
+    file: String,
+    // where the file as it was is kept
+    backup: String,
+    changes: Vec<String>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CreatedSession {
+    pid: PtyHandler,
+    title: Option<String>,
+    // set when the session's profile has its own font settings
+    font: Option<fonts::Font>,
+}
+
+struct PendingClipboardRequest {
+    session_id: PtyHandler,
+    request: ClipboardRequest,
+    requested_at: Instant,
+}
+
 #[derive(Default)]
 struct AppState {
     sessions: RwLock<BTreeMap<PtyHandler, Arc<Session>>>,
     user_configuration: RwLock<usr_conf::UserConfigFS>,
-    startup_notifications: RwLock<Vec<NotificationEvent>>,
+    // None once the frontend took them, later ones are emitted right away
+    startup_notifications: RwLock<Option<Vec<NotificationEvent>>>,
+    history: Mutex<Option<history::CommandHistory>>,
+    focused_session: RwLock<Option<PtyHandler>>,
+    clipboard_requests: Mutex<BTreeMap<u32, PendingClipboardRequest>>,
+    next_clipboard_request_id: AtomicU32,
+    available_shells: RwLock<Option<Vec<shells::DetectedShell>>>,
+    recent_ssh_hosts: RwLock<Vec<String>>,
+    recent_ssh_hosts_path: Option<PathBuf>,
+    config_files: Option<config_layers::ConfigLayerFiles>,
+    config_sources: RwLock<config_layers::ConfigSources>,
+    // the user file's contents the config was last resolved from, held while the file is
+    // written or reloaded so changes to the config do not interleave
+    user_config_file: Mutex<Option<String>>,
+    // a project file whose untrusted settings were ignored, until the user trusts it
+    untrusted_project: RwLock<Option<project_trust::UntrustedProject>>,
+    config_upgrade: RwLock<Option<ConfigUpgradeRequest>>,
+    config_watchers: Mutex<Vec<config_watcher::ConfigWatcher>>,
+    themes_dir: Option<PathBuf>,
 }
 
 type PtyHandler = u32;
@@ -98,28 +196,53 @@ async fn get_startup_notifications(
     #[cfg(debug_assertions)]
     println!("getting startup notifications");
 
-    for notification in state.startup_notifications.read().await.iter() {
+    let notifications = state.startup_notifications.write().await.take();
+    for notification in notifications.unwrap_or_default() {
         app_handle.emit("notification-event", notification).unwrap();
     }
-    state.startup_notifications.write().await.clear();
+    if let Some(untrusted) = state.untrusted_project.read().await.as_ref() {
+        app_handle.emit("project-trust-request", untrusted).unwrap();
+    }
+    if let Some(upgrade) = state.config_upgrade.read().await.as_ref() {
+        app_handle.emit("config-upgrade-request", upgrade).unwrap();
+    }
 
     Ok(())
 }
 
-fn determine_cwd(raw_cwd: Option<String>, referring_session_id: Option<u32>) -> Option<String> {
-    let mut cwd: Option<String> = None;
+// Startup checks that finish in the background are shown with the others if the frontend
+// hasn't asked for them yet
+async fn push_startup_notification<R: Runtime>(
+    notification: NotificationEvent,
+    state: &AppState,
+    app_handle: &AppHandle<R>,
+) {
+    match state.startup_notifications.write().await.as_mut() {
+        Some(notifications) => notifications.push(notification),
+        None => app_handle.emit("notification-event", notification).unwrap(),
+    }
+}
+
+fn determine_cwd(
+    raw_cwd: Option<String>,
+    tracked_cwd: Option<osc::SessionCwd>,
+    referring_session_id: Option<u32>,
+) -> Option<String> {
+    let local_host_name = System::host_name();
 
-    if let Some(cwd_path) = raw_cwd {
-        if let Ok(url) = Url::parse(&cwd_path) {
-            if url.scheme() == "file" {
-                let mut path = url.path().to_string();
-                if cfg!(windows) && path.starts_with('/') {
-                    path = path[1..].to_owned();
-                }
+    // prefer the cwd the backend tracked from the referring session's OSC 7 output
+    let mut cwd = tracked_cwd
+        .filter(|tracked| tracked.is_local(local_host_name.as_deref()))
+        .map(|tracked| tracked.path)
+        .filter(|path| std::fs::metadata(path).is_ok());
+
+    if cwd.is_none() {
+        if let Some(cwd_path) = raw_cwd {
+            if let Some(parsed) = osc::parse_cwd_url(&cwd_path) {
                 #[cfg(debug_assertions)]
-                println!("Parsed path: {:?}", path);
-                if std::fs::metadata(&path).is_ok() {
-                    cwd = Some(path)
+                println!("Parsed path: {:?}", parsed.path);
+                if std::fs::metadata(&parsed.path).is_ok() {
+                    cwd = Some(parsed.path)
                 } else {
                     #[cfg(debug_assertions)]
                     println!("Parsed path does not exist on this machine");
@@ -129,55 +252,129 @@ fn determine_cwd(raw_cwd: Option<String>, referring_session_id: Option<u32>) ->
     }
     if cwd.is_none() {
         if let Some(ref_session_id) = referring_session_id {
-            if let Ok(s_id) = usize::try_from(ref_session_id) {
-                let s = System::new_all();
-                if let Some(process) = s.process(Pid::from(s_id)) {
-                    #[cfg(debug_assertions)]
-                    println!("Path from process: {:?}", process.cwd());
-                    if let Some(cwd_path) = process.cwd() {
-                        if std::fs::metadata(cwd_path).is_ok() {
-                            let cwd_string = cwd_path.to_string_lossy().to_string();
-                            cwd = Some(cwd_string)
-                        }
-                    }
-                }
-            } else {
-                #[cfg(debug_assertions)]
-                println!("Could not convert referring session Id to usize type");
-            }
+            cwd = process_cwd(ref_session_id);
         }
     }
 
     cwd
 }
 
+// only refresh the one process instead of snapshotting every process on the machine
+fn process_cwd(pid: u32) -> Option<String> {
+    let pid = Pid::from_u32(pid);
+    let mut system = System::new();
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        ProcessRefreshKind::new().with_cwd(UpdateKind::Always),
+    );
+    let cwd_path = system.process(pid)?.cwd()?;
+    #[cfg(debug_assertions)]
+    println!("Path from process: {:?}", cwd_path);
+    if std::fs::metadata(cwd_path).is_ok() {
+        Some(cwd_path.to_string_lossy().to_string())
+    } else {
+        None
+    }
+}
+
 #[tauri::command]
 async fn create_session<R: Runtime>(
-    args: Option<Vec<String>>,
+    mut args: Option<Vec<String>>,
     session_size: Option<SessionSize>,
     current_working_directory: Option<String>,
     env: Option<HashMap<String, String>>,
     referring_session_id: Option<u32>,
+    profile: Option<String>,
     state: tauri::State<'_, AppState>,
     app_handle: AppHandle<R>,
-) -> Result<PtyHandler, String> {
+) -> Result<CreatedSession, String> {
     #[cfg(debug_assertions)]
-    println!("Creating session");
+    println!("Creating session with profile {:?}", profile);
 
     let msg = "There was an error creating the shell session.";
 
+    let ssh_host = profile
+        .as_deref()
+        .and_then(|name| name.strip_prefix(ssh_conf::SSH_PROFILE_PREFIX));
+    let known_ssh_host = match ssh_host {
+        Some(host) => is_known_ssh_host(host, &state).await,
+        None => false,
+    };
     let user_config = state.user_configuration.read().await;
+    let launch_settings = match ssh_host {
+        Some(host) if known_ssh_host => Some(usr_conf::LaunchSettings {
+            program: String::from("ssh"),
+            args: Vec::new(),
+            env: user_config.shell.env.clone(),
+            env_file: user_config.shell.env_file.clone(),
+            cwd: None,
+            title: user_config
+                .ssh
+                .hosts
+                .get(host)
+                .and_then(|settings| settings.title.clone())
+                .or(Some(host.to_string())),
+            font: None,
+        }),
+        Some(_) => None,
+        None => usr_conf::resolve_launch_settings(&user_config, profile.as_deref()),
+    }
+    .ok_or_else(|| {
+        emit_error_notification(
+            format!(
+                "Error on usr_conf::resolve_launch_settings - profile {:?} not found",
+                profile
+            ),
+            String::from(msg),
+            format!(
+                "No profile named {:?} is configured",
+                profile.clone().unwrap_or_default()
+            ),
+            app_handle.clone(),
+        );
+        String::from("Unknown profile")
+    })?;
+    drop(user_config);
+    // ssh runs the caller's args on the remote host, which expands them itself
+    if let Some(host) = ssh_host {
+        args = Some(
+            std::iter::once(host.to_string())
+                .chain(args.unwrap_or_default())
+                .collect(),
+        );
+    }
+    let launch_settings =
+        shell_env::resolve_session_launch(launch_settings, args, env).map_err(|e| {
+            emit_error_notification(
+                errfmt!("shell_env::resolve_session_launch", e),
+                String::from(msg),
+                e.clone(),
+                app_handle.clone(),
+            );
+            e
+        })?;
 
-    let args = args.unwrap_or(user_config.shell.args.clone());
     let mut cols: u16 = 200;
     let mut rows: u16 = 100;
     if let Some(size) = session_size {
         cols = size.cols;
         rows = size.rows;
     }
-    let env = env.unwrap_or(user_config.shell.env.clone());
 
-    let cwd = determine_cwd(current_working_directory, referring_session_id);
+    let referring_session = match referring_session_id {
+        Some(id) => state.sessions.read().await.get(&id).cloned(),
+        None => None,
+    };
+    let tracked_cwd = match referring_session {
+        Some(session) => session.cwd.read().await.clone(),
+        None => None,
+    };
+    // a profile's starting directory wins over inheriting the referring session's cwd
+    let profile_cwd = launch_settings
+        .cwd
+        .filter(|path| std::fs::metadata(path).is_ok());
+    let cwd = profile_cwd
+        .or_else(|| determine_cwd(current_working_directory, tracked_cwd, referring_session_id));
     #[cfg(debug_assertions)]
     println!("{:?}", cwd);
 
@@ -219,20 +416,20 @@ async fn create_session<R: Runtime>(
     })?;
 
     #[cfg(debug_assertions)]
-    println!("Launching shell from {}", &user_config.shell.program);
+    println!("Launching shell from {}", &launch_settings.program);
 
     let mut cmd;
-    if user_config.shell.program.is_empty() {
+    if launch_settings.program.is_empty() {
         cmd = CommandBuilder::new_default_prog();
     } else {
-        cmd = CommandBuilder::new(&user_config.shell.program);
+        cmd = CommandBuilder::new(&launch_settings.program);
     }
 
-    cmd.args(args);
+    cmd.args(launch_settings.args);
     if let Some(cwd_path) = cwd {
         cmd.cwd(OsString::from(cwd_path));
     }
-    for (k, v) in env.iter() {
+    for (k, v) in launch_settings.env.iter() {
         cmd.env(OsString::from(k), OsString::from(v));
     }
     let child = pair.slave.spawn_command(cmd).map_err(|e| {
@@ -246,15 +443,28 @@ async fn create_session<R: Runtime>(
     })?;
     drop(pair.slave);
 
+    if let Some(host) = ssh_host {
+        remember_ssh_host(host, &state).await;
+    }
+
     if let Some(handler) = child.process_id() {
         let session = Arc::new(Session {
             master: Mutex::new(pair.master),
             child: Mutex::new(child),
             writer: Mutex::new(writer),
             reader: Mutex::new(reader),
+            osc_parser: Mutex::new(osc::OscParser::new()),
+            command_tracker: Mutex::new(shell_integration::CommandTracker::new()),
+            cwd: RwLock::default(),
+            hyperlinks: Mutex::new(hyperlinks::HyperlinkTracker::new()),
+            output: Mutex::new(detection::OutputBuffer::new()),
         });
         state.sessions.write().await.insert(handler, session);
-        Ok(handler)
+        Ok(CreatedSession {
+            pid: handler,
+            title: launch_settings.title,
+            font: launch_settings.font,
+        })
     } else {
         emit_error_notification(
             String::from("child.process_id() returned None"),
@@ -335,9 +545,459 @@ async fn read_from_session(
         );
         e.to_string()
     })?;
+    process_session_output(pid, &session, &buf[..n], &state, &app_handle).await;
     Ok(String::from_utf8_lossy(&buf[..n]).to_string())
 }
 
+async fn process_session_output<R: Runtime>(
+    pid: PtyHandler,
+    session: &Session,
+    output: &[u8],
+    state: &AppState,
+    app_handle: &AppHandle<R>,
+) {
+    session.output.lock().await.push(output);
+    let sequences = session.osc_parser.lock().await.feed(output);
+    if sequences.is_empty() {
+        return;
+    }
+    let cwd_osc_code = state
+        .user_configuration
+        .read()
+        .await
+        .shell
+        .change_directory_code();
+
+    for sequence in sequences.iter() {
+        if Some(&sequence.code) == cwd_osc_code.as_ref() {
+            if let Some(cwd) = osc::parse_cwd_url(&sequence.data) {
+                #[cfg(debug_assertions)]
+                println!("Session {:?} changed directory to {:?}", pid, cwd);
+                *session.cwd.write().await = Some(cwd);
+            }
+            continue;
+        }
+        if sequence.code == hyperlinks::HYPERLINK_OSC_CODE {
+            session.hyperlinks.lock().await.handle(sequence);
+            continue;
+        }
+        if sequence.code == clipboard::CLIPBOARD_OSC_CODE {
+            if let Some(request) = clipboard::parse_clipboard_request(&sequence.data) {
+                handle_clipboard_request(pid, session, request, state, app_handle).await;
+            }
+            continue;
+        }
+
+        let event = {
+            let output = session.output.lock().await;
+            session
+                .command_tracker
+                .lock()
+                .await
+                .handle(sequence, &output)
+        };
+        match event {
+            Some(CommandEvent::Started) => {
+                let cwd = match session.cwd.read().await.as_ref() {
+                    Some(tracked) => Some(tracked.path.clone()),
+                    None => process_cwd(pid),
+                };
+                session.command_tracker.lock().await.set_cwd(cwd);
+            }
+            Some(CommandEvent::Finished(finished)) => {
+                notify_long_running_command(pid, &finished, state, app_handle).await;
+                record_command(pid, finished, state).await;
+            }
+            None => {}
+        }
+    }
+}
+
+// Every clipboard request goes to the app's log with what became of it
+fn log_clipboard_request(pid: PtyHandler, request: &ClipboardRequest, outcome: &str) {
+    log::info!(
+        "Clipboard {} by session {}: {}",
+        request.access(),
+        pid,
+        outcome
+    );
+}
+
+// Requests the user did not answer in time are dropped as denied
+fn prune_clipboard_requests(pending: &mut BTreeMap<u32, PendingClipboardRequest>) {
+    pending.retain(|_, pending| {
+        let open = pending.requested_at.elapsed() < CLIPBOARD_REQUEST_TIMEOUT;
+        if !open {
+            log_clipboard_request(pending.session_id, &pending.request, "not answered in time");
+        }
+        open
+    });
+}
+
+async fn handle_clipboard_request<R: Runtime>(
+    pid: PtyHandler,
+    session: &Session,
+    request: ClipboardRequest,
+    state: &AppState,
+    app_handle: &AppHandle<R>,
+) {
+    let policy = state.user_configuration.read().await.clipboard.clone();
+    let (permission, text) = match &request {
+        ClipboardRequest::Write { text, .. } => (policy.write, Some(text.clone())),
+        ClipboardRequest::Read { .. } => (policy.read, None),
+    };
+
+    match permission {
+        ClipboardPermission::Allow => {
+            log_clipboard_request(pid, &request, "allowed by config");
+            execute_clipboard_request(session, request, app_handle).await;
+        }
+        ClipboardPermission::Deny => log_clipboard_request(pid, &request, "denied by config"),
+        ClipboardPermission::Prompt => {
+            let mut pending = state.clipboard_requests.lock().await;
+            prune_clipboard_requests(&mut pending);
+            // a program asking again before the user answered is not prompted again
+            if pending.values().any(|request| request.session_id == pid) {
+                log_clipboard_request(pid, &request, "denied, the user has not answered yet");
+                return;
+            }
+            let access = request.access();
+            let request_id = state
+                .next_clipboard_request_id
+                .fetch_add(1, Ordering::Relaxed);
+            pending.insert(
+                request_id,
+                PendingClipboardRequest {
+                    session_id: pid,
+                    request,
+                    requested_at: Instant::now(),
+                },
+            );
+            drop(pending);
+            let permission_request = ClipboardPermissionRequest {
+                request_id,
+                session_id: pid,
+                access: String::from(access),
+                text,
+            };
+            app_handle
+                .emit("clipboard-permission-request", permission_request)
+                .unwrap();
+        }
+    }
+}
+
+async fn execute_clipboard_request<R: Runtime>(
+    session: &Session,
+    request: ClipboardRequest,
+    app_handle: &AppHandle<R>,
+) {
+    match request {
+        ClipboardRequest::Write { text, .. } => {
+            if let Err(e) = app_handle.clipboard().write_text(text) {
+                emit_error_notification(
+                    errfmt!("app_handle.clipboard().write_text", e),
+                    String::from("A program in the terminal could not set the clipboard."),
+                    format!("{:?}", e),
+                    app_handle.clone(),
+                );
+            }
+        }
+        ClipboardRequest::Read { selection } => match app_handle.clipboard().read_text() {
+            Ok(text) => {
+                let response = clipboard::encode_clipboard_response(&selection, &text);
+                if let Err(e) = session.writer.lock().await.write_all(response.as_bytes()) {
+                    emit_error_notification(
+                        errfmt!("session.writer.lock().await.write_all", e),
+                        String::from("There was an error writing to the shell session."),
+                        format!("{:?}", e),
+                        app_handle.clone(),
+                    );
+                }
+            }
+            Err(e) => emit_error_notification(
+                errfmt!("app_handle.clipboard().read_text", e),
+                String::from("A program in the terminal could not read the clipboard."),
+                format!("{:?}", e),
+                app_handle.clone(),
+            ),
+        },
+    }
+}
+
+#[tauri::command]
+async fn respond_to_clipboard_request(
+    request_id: u32,
+    allow: bool,
+    state: tauri::State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    #[cfg(debug_assertions)]
+    println!("Clipboard request {:?} allowed: {:?}", request_id, allow);
+
+    let pending = {
+        let mut pending = state.clipboard_requests.lock().await;
+        prune_clipboard_requests(&mut pending);
+        pending.remove(&request_id)
+    }
+    .ok_or("Unknown clipboard request")?;
+
+    if !allow {
+        log_clipboard_request(pending.session_id, &pending.request, "denied by the user");
+        return Ok(());
+    }
+    log_clipboard_request(pending.session_id, &pending.request, "allowed by the user");
+
+    let session = state
+        .sessions
+        .read()
+        .await
+        .get(&pending.session_id)
+        .ok_or("Unavailable pid")?
+        .clone();
+    execute_clipboard_request(&session, pending.request, &app_handle).await;
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_session_hyperlinks(
+    pid: PtyHandler,
+    state: tauri::State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<String, String> {
+    #[cfg(debug_assertions)]
+    println!("Getting hyperlinks for session {:?}", pid);
+
+    let session = state
+        .sessions
+        .read()
+        .await
+        .get(&pid)
+        .ok_or("Unavailable pid")?
+        .clone();
+    let links = session.hyperlinks.lock().await.links();
+
+    serde_json::to_string(&links).map_err(|e| {
+        emit_error_notification(
+            errfmt!("serde_json::to_string", e),
+            String::from("There was an error getting the links for the shell session."),
+            format!("{:?}", e),
+            app_handle,
+        );
+        e.to_string()
+    })
+}
+
+#[tauri::command]
+async fn open_hyperlink(
+    pid: PtyHandler,
+    index: u64,
+    state: tauri::State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    #[cfg(debug_assertions)]
+    println!("Opening hyperlink {:?} for session {:?}", index, pid);
+
+    let msg = "There was an error opening the link.";
+
+    let session = state
+        .sessions
+        .read()
+        .await
+        .get(&pid)
+        .ok_or("Unavailable pid")?
+        .clone();
+    let uri = session
+        .hyperlinks
+        .lock()
+        .await
+        .get(index)
+        .map(|link| link.uri.clone())
+        .ok_or("Unknown hyperlink")?;
+
+    let allowed_schemes = state
+        .user_configuration
+        .read()
+        .await
+        .hyperlinks
+        .allowed_schemes
+        .clone();
+    if !hyperlinks::is_scheme_allowed(&uri, &allowed_schemes) {
+        emit_error_notification(
+            format!("Blocked hyperlink with disallowed scheme: {}", uri),
+            String::from("The link was not opened because its scheme is not allowed."),
+            format!(
+                "{} - allowed schemes are: {}",
+                uri,
+                allowed_schemes.join(", ")
+            ),
+            app_handle,
+        );
+        return Err(String::from("Scheme not allowed"));
+    }
+
+    // the shell plugin's open scope in tauri.conf.json also limits links to
+    // http, https, mailto and file, whatever the config allows
+    app_handle.shell().open(&uri, None).map_err(|e| {
+        emit_error_notification(
+            errfmt!("shell().open", e),
+            String::from(msg),
+            format!("{:?}", e),
+            app_handle.clone(),
+        );
+        e.to_string()
+    })
+}
+
+#[tauri::command]
+async fn detect_links(
+    pid: PtyHandler,
+    start: Option<u64>,
+    end: Option<u64>,
+    state: tauri::State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<String, String> {
+    #[cfg(debug_assertions)]
+    println!("Detecting links for session {:?}", pid);
+
+    let session = state
+        .sessions
+        .read()
+        .await
+        .get(&pid)
+        .ok_or("Unavailable pid")?
+        .clone();
+
+    // without a range only the lines that fit on the screen are searched
+    let rows = session
+        .master
+        .lock()
+        .await
+        .get_size()
+        .map(|size| size.rows)
+        .unwrap_or(24);
+    let text = {
+        let output = session.output.lock().await;
+        let raw = output.range(
+            start.unwrap_or_else(|| output.last_lines_start(usize::from(rows))),
+            end.unwrap_or(output.end_offset()),
+        );
+        detection::strip_escape_sequences(&raw)
+    };
+    let tracked_cwd = session.cwd.read().await.clone();
+    let cwd = determine_cwd(None, tracked_cwd, Some(pid)).map(std::path::PathBuf::from);
+    let links = detection::detect_links(&text, cwd.as_deref());
+
+    serde_json::to_string(&links).map_err(|e| {
+        emit_error_notification(
+            errfmt!("serde_json::to_string", e),
+            String::from("There was an error finding links in the shell session."),
+            format!("{:?}", e),
+            app_handle,
+        );
+        e.to_string()
+    })
+}
+
+async fn notify_long_running_command<R: Runtime>(
+    pid: PtyHandler,
+    finished: &FinishedCommand,
+    state: &AppState,
+    app_handle: &AppHandle<R>,
+) {
+    let settings = state.user_configuration.read().await.notifications.clone();
+    if !settings.long_running_command_enabled
+        || finished.duration < Duration::from_secs(settings.long_running_command_seconds)
+    {
+        return;
+    }
+
+    let window_focused = app_handle
+        .get_webview_window("main")
+        .and_then(|window| window.is_focused().ok())
+        .unwrap_or(false);
+    let pane_focused = *state.focused_session.read().await == Some(pid);
+    if window_focused && pane_focused {
+        return;
+    }
+
+    let command = if finished.command.is_empty() {
+        String::from("Command")
+    } else {
+        format!("'{}'", finished.command)
+    };
+    let exit_code = match finished.exit_code {
+        Some(code) => code.to_string(),
+        None => String::from("unknown"),
+    };
+    let message = format!(
+        "{} finished after {} with exit code {}",
+        command,
+        format_duration(finished.duration),
+        exit_code
+    );
+
+    if let Err(_e) = app_handle
+        .notification()
+        .builder()
+        .title("Alpha CenTauri Terminal")
+        .body(&message)
+        .show()
+    {
+        #[cfg(debug_assertions)]
+        println!("Error on app_handle.notification().show: {:?}", _e);
+    }
+
+    let notification = NotificationEvent {
+        level: if finished.exit_code == Some(0) { 1 } else { 2 },
+        message,
+        details: format!("Session {}, exit code {}", pid, exit_code),
+    };
+    app_handle.emit("notification-event", notification).unwrap();
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match (seconds / 3600, (seconds % 3600) / 60, seconds % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, s) => format!("{}h {}m {}s", h, m, s),
+    }
+}
+
+async fn record_command(pid: PtyHandler, finished: FinishedCommand, state: &AppState) {
+    if finished.command.is_empty() {
+        return;
+    }
+    if let Some(history) = state.history.lock().await.as_ref() {
+        let started_at = finished
+            .started_at
+            .duration_since(UNIX_EPOCH)
+            .map(|d| i64::try_from(d.as_millis()).unwrap_or(i64::MAX))
+            .unwrap_or_default();
+        let host = match state.sessions.read().await.get(&pid) {
+            Some(session) => session.cwd.read().await.as_ref().map(|c| c.host.clone()),
+            None => None,
+        }
+        .filter(|host| !host.is_empty())
+        .or_else(System::host_name)
+        .unwrap_or_default();
+        let entry = history::NewHistoryEntry {
+            command: finished.command,
+            cwd: finished.cwd,
+            host,
+            exit_code: finished.exit_code,
+            started_at,
+            duration_ms: i64::try_from(finished.duration.as_millis()).unwrap_or(i64::MAX),
+            session_id: pid,
+        };
+        if let Err(_e) = history.record(&entry) {
+            #[cfg(debug_assertions)]
+            println!("Error on history.record: {:?}", _e);
+        }
+    }
+}
+
 #[tauri::command]
 async fn resize(
     pid: PtyHandler,
@@ -385,12 +1045,37 @@ async fn resize(
     }
 }
 
-#[tauri::command]
-async fn end_session(pid: PtyHandler, app_handle: AppHandle) -> Result<(), String> {
-    #[cfg(debug_assertions)]
-    println!("ending session for pid: {:?}", pid);
+// Drops what was waiting on the session, its prompts can no longer be answered
+async fn forget_ended_session(pid: PtyHandler, state: &AppState) {
+    state.clipboard_requests.lock().await.retain(|_, pending| {
+        let other_session = pending.session_id != pid;
+        if !other_session {
+            log_clipboard_request(
+                pid,
+                &pending.request,
+                "session ended before the user answered",
+            );
+        }
+        other_session
+    });
+    let mut focused_session = state.focused_session.write().await;
+    if *focused_session == Some(pid) {
+        *focused_session = None;
+    }
+}
 
-    let msg = "There was an error ending the shell session.";
+#[tauri::command]
+async fn end_session(
+    pid: PtyHandler,
+    state: tauri::State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    #[cfg(debug_assertions)]
+    println!("ending session for pid: {:?}", pid);
+
+    forget_ended_session(pid, &state).await;
+
+    let msg = "There was an error ending the shell session.";
 
     let config = kill_tree::Config {
         signal: String::from("SIGKILL"),
@@ -458,6 +1143,7 @@ async fn wait_for_exit(
             e.to_string()
         })?
         .exit_code();
+    forget_ended_session(pid, &state).await;
     Ok(exitstatus)
 }
 
@@ -537,11 +1223,10 @@ async fn get_user_config(
     #[cfg(debug_assertions)]
     println!("Getting user config");
 
-    let state_config = state.user_configuration.read().await;
-    let config = usr_conf::UserConfigJS {
-        shell: state_config.shell.clone(),
-        keymaps: usr_conf::key_map_to_vector(state_config.keymaps.clone()),
-    };
+    let config = usr_conf::user_config_to_js(
+        &*state.user_configuration.read().await,
+        state.themes_dir.as_deref(),
+    );
 
     serde_json::to_string(&config).map_err(|e| {
         emit_error_notification(
@@ -554,6 +1239,793 @@ async fn get_user_config(
     })
 }
 
+#[tauri::command]
+async fn set_user_config(
+    config: serde_json::Value,
+    state: tauri::State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    #[cfg(debug_assertions)]
+    println!("Setting user config");
+
+    let user_config = usr_conf::parse_user_configuration(config).map_err(|e| {
+        emit_error_notification(
+            errfmt!("usr_conf::parse_user_configuration", e),
+            String::from("The configuration settings were not saved because they are invalid."),
+            format!("{}", e),
+            app_handle.clone(),
+        );
+        format!("{:?}", e)
+    })?;
+    let mut user_config_file = state.user_config_file.lock().await;
+    store_user_config(user_config, &mut user_config_file, &state, &app_handle).await
+}
+
+#[tauri::command]
+async fn update_user_config(
+    pointer: String,
+    value: serde_json::Value,
+    state: tauri::State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    #[cfg(debug_assertions)]
+    println!("Updating user config at {}", pointer);
+
+    // a concurrent update must not be lost between reading the config and saving it
+    let mut user_config_file = state.user_config_file.lock().await;
+    let current_config = state.user_configuration.read().await;
+    let user_config = usr_conf::update_user_configuration(&current_config, &pointer, value)
+        .map_err(|e| {
+            emit_error_notification(
+                errfmt!("usr_conf::update_user_configuration", e),
+                String::from("The configuration setting was not saved because it is invalid."),
+                format!("{}", e),
+                app_handle.clone(),
+            );
+            format!("{:?}", e)
+        })?;
+    drop(current_config);
+    store_user_config(user_config, &mut user_config_file, &state, &app_handle).await
+}
+
+async fn store_user_config<R: Runtime>(
+    user_config: usr_conf::UserConfigFS,
+    user_config_file: &mut Option<String>,
+    state: &AppState,
+    app_handle: &AppHandle<R>,
+) -> Result<(), String> {
+    let msg = "There was an error saving the user configuration file.";
+
+    // saving writes the current version's format, which is only done once the user has agreed
+    // to the upgrade, so they're asked again instead
+    if let Some(upgrade) = state.config_upgrade.read().await.as_ref() {
+        emit_error_notification(
+            String::from("Error on store_user_config - configuration upgrade pending"),
+            String::from(msg),
+            String::from(
+                "Your configuration file was written for an older version, upgrade it before changing settings here.",
+            ),
+            app_handle.clone(),
+        );
+        app_handle.emit("config-upgrade-request", upgrade).unwrap();
+        return Err(String::from("Configuration upgrade pending"));
+    }
+
+    let config_files = state.config_files.as_ref().ok_or_else(|| {
+        emit_error_notification(
+            String::from("Error on store_user_config - no configuration file path"),
+            String::from(msg),
+            String::from("The location of the configuration file is unknown."),
+            app_handle.clone(),
+        );
+        String::from("No configuration file")
+    })?;
+    let settings =
+        config_layers::user_file_settings(&*state.config_sources.read().await, &user_config);
+    usr_conf::save_user_configuration(&config_files.user, &settings).map_err(|e| {
+        emit_error_notification(
+            errfmt!("usr_conf::save_user_configuration", e),
+            String::from(msg),
+            format!("{}", e),
+            app_handle.clone(),
+        );
+        format!("{:?}", e)
+    })?;
+    *user_config_file = std::fs::read_to_string(&config_files.user).ok();
+
+    // project files and --set overrides still apply on top of what was saved
+    let layered = config_layers::resolve_configuration(config_files, false).map_err(|e| {
+        emit_error_notification(
+            errfmt!("config_layers::resolve_configuration", e),
+            String::from(msg),
+            format!("{}", e),
+            app_handle.clone(),
+        );
+        format!("{:?}", e)
+    })?;
+    apply_layered_config(layered, state, app_handle).await;
+    Ok(())
+}
+
+async fn apply_layered_config<R: Runtime>(
+    layered: config_layers::LayeredConfig,
+    state: &AppState,
+    app_handle: &AppHandle<R>,
+) {
+    let config = usr_conf::user_config_to_js(&layered.config, state.themes_dir.as_deref());
+    *state.user_configuration.write().await = layered.config;
+    *state.config_sources.write().await = layered.sources;
+    app_handle.emit("config-changed", config).unwrap();
+    if let Some(untrusted) = &layered.untrusted_project {
+        app_handle.emit("project-trust-request", untrusted).unwrap();
+    }
+    *state.untrusted_project.write().await = layered.untrusted_project;
+    let upgrade = state
+        .config_files
+        .as_ref()
+        .and_then(|files| config_upgrade_request(&files.user, &layered.migrations));
+    // the user was already asked about the same upgrade
+    if upgrade != *state.config_upgrade.read().await {
+        if let Some(upgrade) = &upgrade {
+            app_handle.emit("config-upgrade-request", upgrade).unwrap();
+        }
+    }
+    *state.config_upgrade.write().await = upgrade;
+    if let Some(notification) = ignored_settings_notification(&layered.warnings) {
+        app_handle.emit("notification-event", notification).unwrap();
+    }
+    if let Some(notification) = keymap_notification(&state.user_configuration.read().await.keymaps)
+    {
+        app_handle.emit("notification-event", notification).unwrap();
+    }
+    if let Some(notification) = color_notification(
+        &state.user_configuration.read().await.colors,
+        state.themes_dir.as_deref(),
+    ) {
+        app_handle.emit("notification-event", notification).unwrap();
+    }
+    if let Some(notification) = shell_notification(&state.user_configuration.read().await.shell) {
+        app_handle.emit("notification-event", notification).unwrap();
+    }
+    if let Some(window) = app_handle.get_webview_window("main") {
+        let config = state.user_configuration.read().await;
+        let applied = apply_window_settings(&window, &config, false);
+        if let Some(notification) = window_settings_notification(&config, applied) {
+            app_handle.emit("notification-event", notification).unwrap();
+        }
+    }
+    let font = state.user_configuration.read().await.font.clone();
+    let notification = tauri::async_runtime::spawn_blocking(move || font_notification(&font))
+        .await
+        .ok()
+        .flatten();
+    if let Some(notification) = notification {
+        app_handle.emit("notification-event", notification).unwrap();
+    }
+}
+
+// Transparency can only be chosen when the window is created, so the main window is built here
+// from the config rather than declared in tauri.conf.json. A lower opacity set by a reload
+// takes effect after a restart
+fn build_main_window<R: Runtime>(
+    app: &tauri::App<R>,
+    config: &usr_conf::UserConfigFS,
+) -> tauri::Result<tauri::WebviewWindow<R>> {
+    tauri::WebviewWindowBuilder::new(app, "main", tauri::WebviewUrl::default())
+        .title("Alpha CenTauri Terminal")
+        .min_inner_size(300.0, 200.0)
+        .visible(false)
+        .additional_browser_args("--remote-debugging-port=9222")
+        .decorations(config.window.decorations)
+        .transparent(config.window.opacity < 1.0)
+        .build()
+}
+
+// The startup mode and size only apply when the app starts, the other settings also apply when
+// the config is reloaded
+fn apply_window_settings<R: Runtime>(
+    window: &tauri::WebviewWindow<R>,
+    config: &usr_conf::UserConfigFS,
+    startup: bool,
+) -> tauri::Result<()> {
+    let settings = &config.window;
+    window.set_decorations(settings.decorations)?;
+    window.set_always_on_top(settings.always_on_top)?;
+    if !startup {
+        return Ok(());
+    }
+
+    if let Some((width, height)) = settings.inner_size(&config.font) {
+        window.set_size(tauri::LogicalSize::new(width, height))?;
+    }
+    match settings.startup_mode {
+        usr_conf::StartupMode::Normal => {}
+        usr_conf::StartupMode::Maximized => window.maximize()?,
+        usr_conf::StartupMode::Fullscreen => window.set_fullscreen(true)?,
+    }
+    Ok(())
+}
+
+fn window_settings_notification(
+    config: &usr_conf::UserConfigFS,
+    apply_result: tauri::Result<()>,
+) -> Option<NotificationEvent> {
+    let mut problems = config.window.problems();
+    if let Err(e) = apply_result {
+        problems.push(format!("{}", e));
+    }
+    if problems.is_empty() {
+        return None;
+    }
+    Some(NotificationEvent {
+        level: 2,
+        message: String::from("Some window settings in your configuration could not be applied."),
+        details: problems
+            .iter()
+            .map(|problem| format!("<div>{}</div>", problem))
+            .collect(),
+    })
+}
+
+async fn reload_user_config<R: Runtime>(app_handle: AppHandle<R>) {
+    let state = app_handle.state::<AppState>();
+    let Some(config_files) = &state.config_files else {
+        return;
+    };
+
+    #[cfg(debug_assertions)]
+    println!("Reloading user config from {:?}", config_files.files());
+
+    let mut user_config_file = state.user_config_file.lock().await;
+    *user_config_file = std::fs::read_to_string(&config_files.user).ok();
+    match config_layers::resolve_configuration(config_files, false) {
+        Ok(layered) => apply_layered_config(layered, &state, &app_handle).await,
+        Err(e) => {
+            println!("There was a problem reloading the user config: {:?}", e);
+            let notification = NotificationEvent {
+                level: 2,
+                message: String::from(
+                    "Your configuration settings were not reloaded, the previous settings are still in use.",
+                ),
+                details: format!("{}", e),
+            };
+            app_handle.emit("notification-event", notification).unwrap();
+        }
+    }
+}
+
+// Saving from the app applies the config right away, the watcher then finds the file unchanged
+async fn reload_changed_config<R: Runtime>(app_handle: AppHandle<R>, file_loc: String) {
+    let state = app_handle.state::<AppState>();
+    let is_user_file = state
+        .config_files
+        .as_ref()
+        .is_some_and(|files| files.user == file_loc);
+    if is_user_file
+        && *state.user_config_file.lock().await == std::fs::read_to_string(&file_loc).ok()
+    {
+        return;
+    }
+    reload_user_config(app_handle.clone()).await;
+}
+
+fn config_upgrade_request(
+    config_file_path: &str,
+    migrations: &[String],
+) -> Option<ConfigUpgradeRequest> {
+    if migrations.is_empty() {
+        return None;
+    }
+    Some(ConfigUpgradeRequest {
+        file: config_file_path.to_string(),
+        backup: usr_conf::migration_backup_path(config_file_path),
+        changes: migrations.to_vec(),
+    })
+}
+
+// Rewrites the user's file in the current version's format, the watcher then reloads it
+#[tauri::command]
+async fn upgrade_user_config(
+    state: tauri::State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    #[cfg(debug_assertions)]
+    println!("Upgrading user config");
+
+    let msg = "Your configuration file could not be upgraded.";
+    let Some(upgrade) = state.config_upgrade.read().await.clone() else {
+        return Err(String::from("No upgrade pending"));
+    };
+    let changes = usr_conf::migrate_user_configuration(&upgrade.file).map_err(|e| {
+        emit_error_notification(
+            errfmt!("usr_conf::migrate_user_configuration", e),
+            String::from(msg),
+            format!("{}", e),
+            app_handle.clone(),
+        );
+        e.to_string()
+    })?;
+    *state.config_upgrade.write().await = None;
+
+    let changes: String = changes
+        .iter()
+        .map(|change| format!("<div>{}</div>", change))
+        .collect();
+    let notification = NotificationEvent {
+        level: 1,
+        message: String::from(
+            "Your configuration file was upgraded for this version of Alpha Centauri.",
+        ),
+        details: format!(
+            "{}<div>The previous file was saved as {}</div>",
+            changes, upgrade.backup
+        ),
+    };
+    app_handle.emit("notification-event", notification).unwrap();
+    Ok(())
+}
+
+fn keymap_notification(
+    keymaps: &HashMap<String, keymap::KeyBindings>,
+) -> Option<NotificationEvent> {
+    let problems = usr_conf::keymap_problems(keymaps);
+    if problems.is_empty() {
+        return None;
+    }
+    Some(NotificationEvent {
+        level: 2,
+        message: String::from("Some key bindings in your configuration were ignored."),
+        details: problems
+            .iter()
+            .map(|problem| format!("<div>{}</div>", problem))
+            .collect(),
+    })
+}
+
+fn color_notification(
+    colors: &themes::Colors,
+    themes_dir: Option<&Path>,
+) -> Option<NotificationEvent> {
+    let problems = usr_conf::color_problems(colors, themes_dir);
+    if problems.is_empty() {
+        return None;
+    }
+    Some(NotificationEvent {
+        level: 2,
+        message: String::from("Some colors in your configuration were ignored."),
+        details: problems
+            .iter()
+            .map(|problem| format!("<div>{}</div>", problem))
+            .collect(),
+    })
+}
+
+fn shell_notification(shell: &usr_conf::Shell) -> Option<NotificationEvent> {
+    let problems = shell.problems();
+    if problems.is_empty() {
+        return None;
+    }
+    Some(NotificationEvent {
+        level: 2,
+        message: String::from("Some shell settings in your configuration were ignored."),
+        details: problems
+            .iter()
+            .map(|problem| format!("<div>{}</div>", problem))
+            .collect(),
+    })
+}
+
+fn font_notification(font: &fonts::Font) -> Option<NotificationEvent> {
+    let problems = fonts::font_problems(font, fonts::installed_font_names().as_ref());
+    if problems.is_empty() {
+        return None;
+    }
+    Some(NotificationEvent {
+        level: 2,
+        message: String::from(
+            "Some font settings in your configuration can't be used, other fonts will be shown instead.",
+        ),
+        details: problems
+            .iter()
+            .map(|problem| format!("<div>{}</div>", problem))
+            .collect(),
+    })
+}
+
+fn ignored_settings_notification(
+    warnings: &[config_diagnostics::ConfigDiagnostic],
+) -> Option<NotificationEvent> {
+    if warnings.is_empty() {
+        return None;
+    }
+    Some(NotificationEvent {
+        level: 2,
+        message: String::from("Some settings in your configuration file were ignored."),
+        details: usr_conf::format_diagnostics(warnings),
+    })
+}
+
+// Watches every file a layer was read from, a change to any of them reloads all layers
+fn start_config_watchers<R: Runtime>(
+    app_handle: AppHandle<R>,
+    config_file_paths: &[String],
+    notifications: &mut Vec<NotificationEvent>,
+) -> Vec<config_watcher::ConfigWatcher> {
+    let mut watchers = Vec::new();
+    for config_file_path in config_file_paths {
+        let app_handle = app_handle.clone();
+        let file_loc = config_file_path.clone();
+        let watcher =
+            config_watcher::watch_file(std::path::Path::new(config_file_path), move || {
+                tauri::async_runtime::spawn(reload_changed_config(
+                    app_handle.clone(),
+                    file_loc.clone(),
+                ));
+            });
+
+        match watcher {
+            Ok(watcher) => watchers.push(watcher),
+            Err(e) => {
+                println!(
+                    "There was a problem watching the config file {}: {:?}",
+                    config_file_path, e
+                );
+                notifications.push(NotificationEvent {
+                    level: 2,
+                    message: format!(
+                        "Changes to {} will not be applied until restart.",
+                        config_file_path
+                    ),
+                    details: format!("{:?}", e),
+                });
+            }
+        }
+    }
+    watchers
+}
+
+#[tauri::command]
+async fn get_config_sources(
+    state: tauri::State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<String, String> {
+    #[cfg(debug_assertions)]
+    println!("Getting config sources");
+
+    serde_json::to_string(&*state.config_sources.read().await).map_err(|e| {
+        emit_error_notification(
+            errfmt!("serde_json::to_string", e),
+            String::from("There was an error getting the configuration sources."),
+            format!("{:?}", e),
+            app_handle,
+        );
+        e.to_string()
+    })
+}
+
+#[tauri::command]
+async fn get_commands(app_handle: AppHandle) -> Result<String, String> {
+    #[cfg(debug_assertions)]
+    println!("Getting commands");
+
+    serde_json::to_string(command_registry::COMMANDS).map_err(|e| {
+        emit_error_notification(
+            errfmt!("serde_json::to_string", e),
+            String::from("There was an error getting the list of commands."),
+            format!("{:?}", e),
+            app_handle,
+        );
+        e.to_string()
+    })
+}
+
+// Converts an iTerm2, Alacritty, Windows Terminal or base16 theme into the themes directory,
+// returning the name to set as colors.theme
+#[tauri::command]
+async fn import_theme(
+    path: String,
+    state: tauri::State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<String, String> {
+    #[cfg(debug_assertions)]
+    println!("Importing theme from {}", path);
+
+    let msg = "The theme could not be imported.";
+    let themes_dir = state.themes_dir.as_ref().ok_or_else(|| {
+        emit_error_notification(
+            String::from("Error on import_theme - no themes directory"),
+            String::from(msg),
+            String::from("Neither the home directory nor XDG_CONFIG_HOME could be found."),
+            app_handle.clone(),
+        );
+        String::from("No themes directory")
+    })?;
+    let name = themes::import_theme_file(Path::new(&path), themes_dir).map_err(|e| {
+        emit_error_notification(
+            errfmt!("themes::import_theme_file", e),
+            String::from(msg),
+            e.clone(),
+            app_handle.clone(),
+        );
+        e
+    })?;
+
+    // re-importing the theme in use changes the colors
+    let theme_in_use = state
+        .user_configuration
+        .read()
+        .await
+        .colors
+        .theme
+        .as_deref()
+        == Some(name.as_str());
+    if theme_in_use {
+        reload_user_config(app_handle.clone()).await;
+    }
+
+    serde_json::to_string(&name).map_err(|e| e.to_string())
+}
+
+// Trusts the project file whose settings were ignored, as long as its content is still what the
+// user was shown
+#[tauri::command]
+async fn trust_project_config(
+    hash: String,
+    state: tauri::State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    #[cfg(debug_assertions)]
+    println!("Trusting project config {}", hash);
+
+    let msg = "The project configuration could not be trusted.";
+    let untrusted = state.untrusted_project.read().await.clone();
+    let Some(untrusted) = untrusted.filter(|untrusted| untrusted.hash == hash) else {
+        emit_error_notification(
+            String::from("Error on trust_project_config - the project file changed"),
+            String::from(msg),
+            String::from("The file changed since it was checked, review it again."),
+            app_handle.clone(),
+        );
+        return Err(String::from("Project file changed"));
+    };
+    let trust_file = state
+        .config_files
+        .as_ref()
+        .and_then(|files| files.trusted_projects.clone())
+        .ok_or_else(|| {
+            emit_error_notification(
+                String::from("Error on trust_project_config - no state directory"),
+                String::from(msg),
+                String::from("Neither the home directory nor XDG_STATE_HOME could be found."),
+                app_handle.clone(),
+            );
+            String::from("No state directory")
+        })?;
+    project_trust::trust_project(&trust_file, &untrusted.file, &untrusted.hash).map_err(|e| {
+        emit_error_notification(
+            errfmt!("project_trust::trust_project", e),
+            String::from(msg),
+            e.clone(),
+            app_handle.clone(),
+        );
+        e
+    })?;
+
+    reload_user_config(app_handle.clone()).await;
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_focused_session(
+    pid: Option<PtyHandler>,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    #[cfg(debug_assertions)]
+    println!("Focused session {:?}", pid);
+
+    *state.focused_session.write().await = pid;
+    Ok(())
+}
+
+// No pane has focus while the window doesn't, the terminal reports its focus again when the
+// window gets it back
+fn on_window_event<R: Runtime>(window: &tauri::Window<R>, event: &tauri::WindowEvent) {
+    if let tauri::WindowEvent::Focused(false) = event {
+        let app_handle = window.app_handle().clone();
+        tauri::async_runtime::spawn(async move {
+            // events can arrive before setup managed the state
+            if let Some(state) = app_handle.try_state::<AppState>() {
+                *state.focused_session.write().await = None;
+            }
+        });
+    }
+}
+
+#[tauri::command]
+async fn search_history(
+    filter: Option<history::HistoryFilter>,
+    state: tauri::State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<String, String> {
+    #[cfg(debug_assertions)]
+    println!("Searching history with {:?}", filter);
+
+    let msg = "There was an error searching the command history.";
+
+    let history = state.history.lock().await;
+    let entries = history
+        .as_ref()
+        .ok_or_else(|| {
+            emit_error_notification(
+                String::from("Error on search_history - history database is not open"),
+                String::from(msg),
+                String::from("The command history database could not be opened at startup."),
+                app_handle.clone(),
+            );
+            String::from("History unavailable")
+        })?
+        .search(&filter.unwrap_or_default())
+        .map_err(|e| {
+            emit_error_notification(
+                errfmt!("history.search", e),
+                String::from(msg),
+                format!("{}", e),
+                app_handle.clone(),
+            );
+            e.to_string()
+        })?;
+
+    serde_json::to_string(&entries).map_err(|e| {
+        emit_error_notification(
+            errfmt!("serde_json::to_string", e),
+            String::from(msg),
+            format!("{:?}", e),
+            app_handle,
+        );
+        e.to_string()
+    })
+}
+
+#[tauri::command]
+async fn get_available_shells(
+    refresh: Option<bool>,
+    state: tauri::State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<String, String> {
+    #[cfg(debug_assertions)]
+    println!("Getting available shells, refresh: {:?}", refresh);
+
+    let msg = "There was an error finding the installed shells.";
+
+    let cached = state.available_shells.read().await.clone();
+    let available_shells = match cached {
+        Some(available_shells) if !refresh.unwrap_or(false) => available_shells,
+        _ => {
+            let discovered = tauri::async_runtime::spawn_blocking(shells::discover_shells)
+                .await
+                .map_err(|e| {
+                    emit_error_notification(
+                        errfmt!("spawn_blocking(shells::discover_shells)", e),
+                        String::from(msg),
+                        format!("{:?}", e),
+                        app_handle.clone(),
+                    );
+                    e.to_string()
+                })?;
+            *state.available_shells.write().await = Some(discovered.clone());
+            discovered
+        }
+    };
+
+    serde_json::to_string(&available_shells).map_err(|e| {
+        emit_error_notification(
+            errfmt!("serde_json::to_string", e),
+            String::from(msg),
+            format!("{:?}", e),
+            app_handle,
+        );
+        e.to_string()
+    })
+}
+
+#[tauri::command]
+async fn list_monospace_fonts(app_handle: AppHandle) -> Result<String, String> {
+    #[cfg(debug_assertions)]
+    println!("Listing monospace fonts");
+
+    let msg = "There was an error finding the installed fonts.";
+
+    let families = tauri::async_runtime::spawn_blocking(fonts::list_monospace_fonts)
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|families| families)
+        .map_err(|e| {
+            emit_error_notification(
+                errfmt!("fonts::list_monospace_fonts", e),
+                String::from(msg),
+                e.clone(),
+                app_handle.clone(),
+            );
+            e
+        })?;
+
+    serde_json::to_string(&families).map_err(|e| {
+        emit_error_notification(
+            errfmt!("serde_json::to_string", e),
+            String::from(msg),
+            format!("{:?}", e),
+            app_handle,
+        );
+        e.to_string()
+    })
+}
+
+// A made up profile name should not connect somewhere the user never configured
+async fn is_known_ssh_host(host: &str, state: &AppState) -> bool {
+    let config_hosts = match ssh_conf::user_ssh_config_path() {
+        Some(path) => {
+            tauri::async_runtime::spawn_blocking(move || ssh_conf::parse_ssh_config(&path))
+                .await
+                .unwrap_or_default()
+        }
+        None => Vec::new(),
+    };
+    let recent_hosts = state.recent_ssh_hosts.read().await;
+    ssh_conf::is_known_host(host, &config_hosts, &recent_hosts)
+}
+
+async fn remember_ssh_host(host: &str, state: &AppState) {
+    let mut recent_hosts = state.recent_ssh_hosts.write().await;
+    ssh_conf::mark_recent(&mut recent_hosts, host);
+    if let Some(path) = &state.recent_ssh_hosts_path {
+        if let Err(_e) = ssh_conf::save_recent_hosts(path, &recent_hosts) {
+            #[cfg(debug_assertions)]
+            println!("Error on ssh_conf::save_recent_hosts: {:?}", _e);
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_ssh_hosts(
+    state: tauri::State<'_, AppState>,
+    app_handle: AppHandle,
+) -> Result<String, String> {
+    #[cfg(debug_assertions)]
+    println!("Getting ssh hosts");
+
+    let msg = "There was an error reading your ssh configuration.";
+
+    let hosts = match ssh_conf::user_ssh_config_path() {
+        Some(path) => {
+            tauri::async_runtime::spawn_blocking(move || ssh_conf::parse_ssh_config(&path))
+                .await
+                .map_err(|e| {
+                    emit_error_notification(
+                        errfmt!("spawn_blocking(ssh_conf::parse_ssh_config)", e),
+                        String::from(msg),
+                        format!("{:?}", e),
+                        app_handle.clone(),
+                    );
+                    e.to_string()
+                })?
+        }
+        None => Vec::new(),
+    };
+    let host_settings = state.user_configuration.read().await.ssh.hosts.clone();
+    let recent_hosts = state.recent_ssh_hosts.read().await.clone();
+    let profiles = ssh_conf::host_profiles(hosts, &host_settings, &recent_hosts);
+
+    serde_json::to_string(&profiles).map_err(|e| {
+        emit_error_notification(
+            errfmt!("serde_json::to_string", e),
+            String::from(msg),
+            format!("{:?}", e),
+            app_handle,
+        );
+        e.to_string()
+    })
+}
+
 #[tauri::command]
 async fn get_system_info(app_handle: AppHandle) -> Result<String, String> {
     #[cfg(debug_assertions)]
@@ -581,9 +2053,39 @@ async fn get_system_info(app_handle: AppHandle) -> Result<String, String> {
     })
 }
 
+// `convert <input> <output>` rewrites a config file in the format of the output extension,
+// returning the process exit code
+fn convert_config_file(args: &[String]) -> i32 {
+    let [input, output] = args else {
+        eprintln!("Usage: convert <input> <output>");
+        return 2;
+    };
+
+    match usr_conf::convert_user_configuration(input, output) {
+        Ok(warnings) => {
+            for warning in warnings {
+                eprintln!("{}: warning: {}", input, warning);
+            }
+            println!("Converted {} to {}", input, output);
+            0
+        }
+        Err(usr_conf::UserConfigError::Invalid(diagnostics)) => {
+            for diagnostic in diagnostics {
+                eprintln!("{}: error: {}", input, diagnostic);
+            }
+            1
+        }
+        Err(e) => {
+            eprintln!("Unable to convert {}: {:?}", input, e);
+            1
+        }
+    }
+}
+
 fn setup(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>> {
     let mut arg_path: Option<String> = None;
     let mut save_default_config = true;
+    let mut overrides: Vec<String> = Vec::new();
 
     // get the command line args via the Tauri built-in function
     if let Ok(matches) = app.cli().matches() {
@@ -600,66 +2102,252 @@ fn setup(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
         };
+        // --set may be repeated
+        if let Some(arg) = matches.args.get("set") {
+            match &arg.value {
+                serde_json::Value::String(value) => overrides.push(value.clone()),
+                serde_json::Value::Array(values) => overrides.extend(
+                    values
+                        .iter()
+                        .filter_map(|value| value.as_str())
+                        .map(String::from),
+                ),
+                _ => {}
+            }
+        }
     }
 
+    let base_dirs = app_dirs::BaseDirs::from_env();
+    let themes_dir = base_dirs.themes_dir();
+    let mut notifications = Vec::new();
     let config_file_path = arg_path.or_else(|| {
         #[cfg(debug_assertions)]
         println!("Attempting to retrieve user config file");
-        let home_path = home_dir().unwrap();
-
-        #[cfg(target_os = "windows")]
-        let default_path = Some(format!(
-            "{}\\.alphacentauri.config.json",
-            home_path.to_str().unwrap()
-        ));
 
-        #[cfg(not(target_os = "windows"))]
-        let default_path = Some(format!(
-            "{}/.alphacentauri.config.json",
-            home_path.to_str().unwrap()
-        ));
+        match base_dirs.user_config_file() {
+            Ok(path) => Some(path.to_string_lossy().to_string()),
+            Err(e) => {
+                println!("There was a problem locating the user config: {}", e);
+                notifications.push(NotificationEvent {
+                    level: 2,
+                    message: String::from(
+                        "Your configuration file could not be found, the default settings are in use.",
+                    ),
+                    details: e,
+                });
+                None
+            }
+        }
+    });
 
-        default_path
+    let config_files = config_file_path.map(|config_file_path| config_layers::ConfigLayerFiles {
+        system: config_layers::system_config_file(),
+        project: std::env::current_dir().ok().and_then(|cwd| {
+            config_layers::find_project_config(&cwd, &config_file_path, base_dirs.home.as_deref())
+        }),
+        user: config_file_path,
+        overrides,
+        trusted_projects: base_dirs
+            .state_dir()
+            .map(|state_dir| state_dir.join(TRUSTED_PROJECTS_FILE_NAME)),
+    });
+    let resolved = config_files.as_ref().map(|config_files| {
+        config_layers::resolve_configuration(config_files, save_default_config)
     });
+    let mut untrusted_project = None;
+    let mut config_upgrade = None;
+    let (user_config, config_sources) = match resolved {
+        Some(Ok(layered)) => {
+            untrusted_project = layered.untrusted_project;
+            config_upgrade = config_files.as_ref().and_then(|config_files| {
+                config_upgrade_request(&config_files.user, &layered.migrations)
+            });
+            notifications.extend(ignored_settings_notification(&layered.warnings));
+            notifications.extend(keymap_notification(&layered.config.keymaps));
+            notifications.extend(color_notification(
+                &layered.config.colors,
+                themes_dir.as_deref(),
+            ));
+            notifications.extend(shell_notification(&layered.config.shell));
+            (layered.config, layered.sources)
+        }
+        Some(Err(e)) => {
+            println!("There was a problem getting the user config: {:?}", e);
+            notifications.push(NotificationEvent {
+                level: 2,
+                message: String::from("There was an error getting your configuration settings."),
+                details: format!("{}", e),
+            });
+            (
+                usr_conf::generate_default_user_config(),
+                config_layers::ConfigSources::default(),
+            )
+        }
+        None => (
+            usr_conf::generate_default_user_config(),
+            config_layers::ConfigSources::default(),
+        ),
+    };
 
-    let mut notification_event = None;
-    let user_config =
-        match usr_conf::get_user_configuration(&config_file_path.unwrap(), save_default_config) {
-            Ok(user_config) => user_config,
+    let window = build_main_window(app, &user_config)?;
+    let applied = apply_window_settings(&window, &user_config, true);
+    notifications.extend(window_settings_notification(&user_config, applied));
+
+    let mut watched_files: Vec<String> = Vec::new();
+    if let Some(config_files) = &config_files {
+        // only where the app keeps its own files, not as another dotfile in the home directory
+        if base_dirs.is_app_config_file(std::path::Path::new(&config_files.user)) {
+            if let Err(e) = usr_conf::publish_user_config_schema(&config_files.user) {
+                println!(
+                    "There was a problem writing the user config schema: {:?}",
+                    e
+                );
+            }
+        }
+        watched_files.extend(config_files.files().into_iter().map(String::from));
+        watched_files.extend(
+            config_sources
+                .layers
+                .iter()
+                .filter(|layer| layer.source.kind == config_layers::ConfigLayerKind::Include)
+                .filter_map(|layer| layer.source.location.clone()),
+        );
+    }
+
+    // older versions kept state in the app data directory, it is moved on first use
+    let state_dir = base_dirs.state_dir();
+    let legacy_data_dir = app.path().app_data_dir().ok();
+    let state_file = |file_name: &str| {
+        let path = state_dir.as_ref()?.join(file_name);
+        if let Some(legacy_data_dir) = &legacy_data_dir {
+            app_dirs::adopt_legacy_state_file(&legacy_data_dir.join(file_name), &path);
+        }
+        Some(path)
+    };
+    if state_dir.is_none() {
+        println!("There was a problem resolving the state directory");
+        notifications.push(NotificationEvent {
+            level: 2,
+            message: String::from("Command history and recent SSH hosts will not be recorded."),
+            details: String::from("Neither the home directory nor XDG_STATE_HOME could be found."),
+        });
+    }
+
+    let history = match state_file(HISTORY_FILE_NAME) {
+        Some(path) => match history::CommandHistory::open(&path) {
+            Ok(history) => Some(history),
             Err(e) => {
-                println!("There was a problem getting the user config: {:?}", e);
-                notification_event = Some(NotificationEvent {
+                println!("There was a problem opening the command history: {:?}", e);
+                notifications.push(NotificationEvent {
                     level: 2,
-                    message: String::from(
-                        "There was an error getting your configuration settings.",
-                    ),
+                    message: String::from("Command history will not be recorded."),
                     details: format!("{}", e),
                 });
-                usr_conf::generate_default_user_config()
+                None
             }
-        };
+        },
+        None => None,
+    };
+
+    let recent_ssh_hosts_path = state_file(RECENT_SSH_HOSTS_FILE_NAME);
+    let recent_ssh_hosts = match &recent_ssh_hosts_path {
+        Some(path) => ssh_conf::load_recent_hosts(path),
+        None => Vec::new(),
+    };
 
+    let user_config_file = config_files
+        .as_ref()
+        .and_then(|files| std::fs::read_to_string(&files.user).ok());
+    let font = user_config.font.clone();
     let state = AppState {
         sessions: RwLock::default(),
         user_configuration: RwLock::new(user_config),
-        startup_notifications: match notification_event {
-            Some(event) => RwLock::new(Vec::from([event])),
-            None => RwLock::default(),
-        },
+        startup_notifications: RwLock::new(Some(notifications)),
+        history: Mutex::new(history),
+        focused_session: RwLock::default(),
+        clipboard_requests: Mutex::default(),
+        next_clipboard_request_id: AtomicU32::default(),
+        available_shells: RwLock::default(),
+        recent_ssh_hosts: RwLock::new(recent_ssh_hosts),
+        recent_ssh_hosts_path,
+        config_files,
+        config_sources: RwLock::new(config_sources),
+        user_config_file: Mutex::new(user_config_file),
+        untrusted_project: RwLock::new(untrusted_project),
+        config_upgrade: RwLock::new(config_upgrade),
+        config_watchers: Mutex::default(),
+        themes_dir,
     };
 
     app.manage(state);
 
+    // a change reloads through the managed state, so watching only starts once it is there
+    let app_handle = app.handle().clone();
+    tauri::async_runtime::spawn(async move {
+        let mut notifications = Vec::new();
+        let watchers =
+            start_config_watchers(app_handle.clone(), &watched_files, &mut notifications);
+        let state = app_handle.state::<AppState>();
+        *state.config_watchers.lock().await = watchers;
+        for notification in notifications {
+            push_startup_notification(notification, &state, &app_handle).await;
+        }
+    });
+
+    // fc-list can take a while on systems with many fonts
+    let app_handle = app.handle().clone();
+    tauri::async_runtime::spawn(async move {
+        let notification = tauri::async_runtime::spawn_blocking(move || font_notification(&font))
+            .await
+            .ok()
+            .flatten();
+        if let Some(notification) = notification {
+            let state = app_handle.state::<AppState>();
+            push_startup_notification(notification, &state, &app_handle).await;
+        }
+    });
+
     Ok(())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // convert runs before the app is built, so it opens no window and its exit code is returned
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("convert") {
+        // release builds on Windows have no console of their own, print to the one it ran from
+        #[cfg(windows)]
+        unsafe {
+            use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+            AttachConsole(ATTACH_PARENT_PROCESS);
+        }
+        std::process::exit(convert_config_file(&args[1..]));
+    }
+
+    // the plugin joins the file name to the app config directory, an absolute path replaces it
+    let mut window_state = tauri_plugin_window_state::Builder::default();
+    if let Some(state_dir) = app_dirs::BaseDirs::from_env().state_dir() {
+        window_state = window_state.with_filename(
+            state_dir
+                .join(WINDOW_STATE_FILE_NAME)
+                .to_string_lossy()
+                .to_string(),
+        );
+    }
+
     tauri::Builder::default()
+        .plugin(
+            tauri_plugin_log::Builder::new()
+                .level(log::LevelFilter::Info)
+                .build(),
+        )
         .plugin(tauri_plugin_cli::init())
         .plugin(tauri_plugin_clipboard_manager::init())
+        .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_shell::init())
         .setup(setup)
-        .plugin(tauri_plugin_window_state::Builder::default().build())
+        .on_window_event(on_window_event)
+        .plugin(window_state.build())
         .invoke_handler(tauri::generate_handler![
             create_session,
             write_to_session,
@@ -670,7 +2358,23 @@ pub fn run() {
             check_exit_status,
             get_startup_notifications,
             get_user_config,
-            get_system_info
+            get_system_info,
+            search_history,
+            set_focused_session,
+            respond_to_clipboard_request,
+            get_session_hyperlinks,
+            open_hyperlink,
+            trust_project_config,
+            upgrade_user_config,
+            detect_links,
+            get_available_shells,
+            list_monospace_fonts,
+            get_ssh_hosts,
+            set_user_config,
+            update_user_config,
+            get_config_sources,
+            get_commands,
+            import_theme
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -689,7 +2393,7 @@ mod tests {
         let raw_cwd = format!("file://machine/{}", expected_path);
         let expected = expected_path.replace("\\", "/");
 
-        let actual = match determine_cwd(Some(raw_cwd), None) {
+        let actual = match determine_cwd(Some(raw_cwd), None, None) {
             Some(cwd) => cwd,
             None => String::new(),
         };
@@ -698,4 +2402,30 @@ mod tests {
 
         let _ = dir.close();
     }
+
+    #[test]
+    fn determine_cwd_prefers_tracked_cwd() {
+        let tracked_dir = TempDir::new("tracked").unwrap();
+        let raw_dir = TempDir::new("raw").unwrap();
+        let expected = tracked_dir.path().to_string_lossy().to_string();
+        let tracked_cwd = osc::SessionCwd {
+            host: String::from("localhost"),
+            path: expected.clone(),
+        };
+        let raw_cwd = format!("file://machine/{}", raw_dir.path().to_string_lossy());
+
+        let actual = determine_cwd(Some(raw_cwd), Some(tracked_cwd), None);
+
+        assert_eq!(actual, Some(expected));
+
+        let _ = tracked_dir.close();
+        let _ = raw_dir.close();
+    }
+
+    #[test]
+    fn format_duration_includes_largest_units() {
+        assert_eq!(format_duration(Duration::from_secs(42)), "42s");
+        assert_eq!(format_duration(Duration::from_secs(1250)), "20m 50s");
+        assert_eq!(format_duration(Duration::from_secs(7322)), "2h 2m 2s");
+    }
 }
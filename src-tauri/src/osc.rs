@@ -0,0 +1,237 @@
+use url::Url;
+
+// Operating System Command (OSC) sequences have the form `ESC ] <code> ; <data> <terminator>`
+// where the terminator is either BEL or the two byte String Terminator `ESC \`.
+// Output from the pty arrives in arbitrary chunks, so the parser keeps its state between calls.
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+const OSC_START: u8 = b']';
+const ST_END: u8 = b'\\';
+
+// OSC 52 payloads can be large, but anything beyond this is most likely garbage
+const MAX_SEQUENCE_LENGTH: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscSequence {
+    pub code: String,
+    pub data: String,
+    // byte offsets into the session output stream, from the ESC up to just past the terminator
+    pub start: u64,
+    pub end: u64,
+}
+
+// Location reported by the shell through OSC 7 (file://hostname/path)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionCwd {
+    pub host: String,
+    pub path: String,
+}
+
+impl SessionCwd {
+    // a cwd reported from inside an ssh session does not exist on this machine
+    pub fn is_local(&self, local_host_name: Option<&str>) -> bool {
+        self.host.is_empty()
+            || self.host.eq_ignore_ascii_case("localhost")
+            || local_host_name.is_some_and(|name| self.host.eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+enum ParserState {
+    #[default]
+    Ground,
+    Escape,
+    Osc,
+    OscEscape,
+}
+
+#[derive(Debug, Default)]
+pub struct OscParser {
+    state: ParserState,
+    buffer: Vec<u8>,
+    position: u64,
+    sequence_start: u64,
+}
+
+impl OscParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<OscSequence> {
+        let mut sequences = Vec::new();
+
+        for &byte in bytes {
+            self.position += 1;
+            match self.state {
+                ParserState::Ground => {
+                    if byte == ESC {
+                        self.sequence_start = self.position - 1;
+                        self.state = ParserState::Escape;
+                    }
+                }
+                ParserState::Escape => {
+                    self.state = match byte {
+                        OSC_START => {
+                            self.buffer.clear();
+                            ParserState::Osc
+                        }
+                        ESC => {
+                            self.sequence_start = self.position - 1;
+                            ParserState::Escape
+                        }
+                        _ => ParserState::Ground,
+                    };
+                }
+                ParserState::Osc => match byte {
+                    BEL => {
+                        sequences.push(self.take_sequence());
+                        self.state = ParserState::Ground;
+                    }
+                    ESC => self.state = ParserState::OscEscape,
+                    _ => self.push_byte(byte),
+                },
+                ParserState::OscEscape => {
+                    if byte == ST_END {
+                        sequences.push(self.take_sequence());
+                        self.state = ParserState::Ground;
+                    } else {
+                        // an escape inside an OSC that is not a String Terminator aborts the sequence
+                        self.buffer.clear();
+                        self.sequence_start = self.position - 2;
+                        self.state = if byte == OSC_START {
+                            ParserState::Osc
+                        } else {
+                            ParserState::Ground
+                        };
+                    }
+                }
+            }
+        }
+
+        sequences
+    }
+
+    fn push_byte(&mut self, byte: u8) {
+        if self.buffer.len() < MAX_SEQUENCE_LENGTH {
+            self.buffer.push(byte);
+        } else {
+            #[cfg(debug_assertions)]
+            println!("OSC sequence exceeded maximum length, discarding");
+            self.buffer.clear();
+            self.state = ParserState::Ground;
+        }
+    }
+
+    fn take_sequence(&mut self) -> OscSequence {
+        let raw = String::from_utf8_lossy(&self.buffer).to_string();
+        self.buffer.clear();
+        let (code, data) = match raw.split_once(';') {
+            Some((code, data)) => (code.to_string(), data.to_string()),
+            None => (raw, String::new()),
+        };
+        OscSequence {
+            code,
+            data,
+            start: self.sequence_start,
+            end: self.position,
+        }
+    }
+}
+
+pub fn parse_cwd_url(data: &str) -> Option<SessionCwd> {
+    let url = Url::parse(data).ok()?;
+    if url.scheme() != "file" {
+        return None;
+    }
+    let mut path = percent_decode(url.path());
+    if cfg!(windows) && path.starts_with('/') {
+        path = path[1..].to_owned();
+    }
+    Some(SessionCwd {
+        host: url.host_str().unwrap_or_default().to_string(),
+        path,
+    })
+}
+
+pub fn percent_decode(raw: &str) -> String {
+    let bytes = raw.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feed_parses_bel_and_st_terminated_sequences() {
+        let mut parser = OscParser::new();
+
+        let actual = parser.feed(b"text\x1b]0;title\x07more\x1b]133;D;1\x1b\\end");
+
+        assert_eq!(
+            actual,
+            Vec::from([
+                OscSequence {
+                    code: "0".to_string(),
+                    data: "title".to_string(),
+                    start: 4,
+                    end: 14,
+                },
+                OscSequence {
+                    code: "133".to_string(),
+                    data: "D;1".to_string(),
+                    start: 18,
+                    end: 29,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_cwd_url_decodes_host_and_path() {
+        let actual = parse_cwd_url("file://machine/home/user/my%20project").unwrap();
+
+        assert_eq!(actual.host, "machine");
+        #[cfg(not(target_os = "windows"))]
+        assert_eq!(actual.path, "/home/user/my project");
+        assert!(actual.is_local(Some("MACHINE")));
+        assert!(!actual.is_local(Some("other")));
+    }
+
+    #[test]
+    fn feed_parses_sequences_split_across_chunks() {
+        let mut parser = OscParser::new();
+
+        let first = parser.feed(b"output\x1b]7;file://host");
+        let second = parser.feed(b"/home/user\x1b");
+        let third = parser.feed(b"\\prompt");
+
+        assert!(first.is_empty());
+        assert!(second.is_empty());
+        assert_eq!(
+            third,
+            Vec::from([OscSequence {
+                code: "7".to_string(),
+                data: "file://host/home/user".to_string(),
+                start: 6,
+                end: 33,
+            }])
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, fs, path::Path};
+
+// Settings that run programs or grant permissions, a project file only applies them once the
+// user trusts it since it comes with whatever repository was checked out
+const UNTRUSTED_SETTINGS: &[&str] = &[
+    "shell.program",
+    "shell.args",
+    "shell.env",
+    "shell.envFile",
+    "profiles",
+    "defaultProfile",
+    "clipboard.read",
+    "clipboard.write",
+    "hyperlinks.allowedSchemes",
+    "keymaps.terminal:send_text",
+];
+
+// A project file that set untrusted settings, they were left out of the config
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UntrustedProject {
+    pub file: String,
+    // of the file and everything it includes, trusting it trusts exactly this content
+    pub hash: String,
+    pub ignored: Vec<String>,
+}
+
+fn canonical(file_loc: &str) -> String {
+    fs::canonicalize(file_loc)
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or(file_loc.to_string())
+}
+
+pub fn content_hash(file_locs: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for file_loc in file_locs {
+        hasher.update(file_loc.as_bytes());
+        hasher.update([0]);
+        hasher.update(fs::read(file_loc).unwrap_or_default());
+        hasher.update([0]);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// Canonical project file path to the hash the user trusted
+fn load_trusted_projects(trust_file: &Path) -> BTreeMap<String, String> {
+    fs::read_to_string(trust_file)
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+// Without a trust file nothing can be trusted
+pub fn is_trusted(trust_file: Option<&Path>, file_loc: &str, hash: &str) -> bool {
+    trust_file.is_some_and(|trust_file| {
+        load_trusted_projects(trust_file).get(&canonical(file_loc)) == Some(&hash.to_string())
+    })
+}
+
+pub fn trust_project(trust_file: &Path, file_loc: &str, hash: &str) -> Result<(), String> {
+    let mut trusted = load_trusted_projects(trust_file);
+    trusted.insert(canonical(file_loc), hash.to_string());
+    if let Some(parent) = trust_file.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let contents = serde_json::to_string_pretty(&trusted).map_err(|e| e.to_string())?;
+    fs::write(trust_file, contents).map_err(|e| e.to_string())
+}
+
+fn remove_path(value: &mut Value, path: &str) -> bool {
+    let Some(object) = value.as_object_mut() else {
+        return false;
+    };
+    match path.split_once('.') {
+        Some((key, rest)) => object
+            .get_mut(key)
+            .is_some_and(|value| remove_path(value, rest)),
+        None => object.remove(path).is_some(),
+    }
+}
+
+// Takes the untrusted settings out of a layer's value, returning the ones it had
+pub fn remove_untrusted_settings(value: &mut Value) -> Vec<String> {
+    UNTRUSTED_SETTINGS
+        .iter()
+        .filter(|path| remove_path(value, path))
+        .map(|path| path.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn remove_untrusted_settings_keeps_other_settings() {
+        let mut value = serde_json::json!({
+            "shell": {"program": "./evil.sh", "bell": false},
+            "clipboard": {"read": "allow"},
+            "keymaps": {"terminal:send_text": {"keys": "ctrl+x"}, "edit:copy": "ctrl+c"}
+        });
+
+        let actual = remove_untrusted_settings(&mut value);
+
+        assert_eq!(
+            actual,
+            Vec::from([
+                String::from("shell.program"),
+                String::from("clipboard.read"),
+                String::from("keymaps.terminal:send_text")
+            ])
+        );
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "shell": {"bell": false},
+                "clipboard": {},
+                "keymaps": {"edit:copy": "ctrl+c"}
+            })
+        );
+    }
+
+    #[test]
+    fn trust_covers_only_the_trusted_content() {
+        let dir = TempDir::new("usr_home").unwrap();
+        let trust_file = dir.path().join("state").join("trusted_projects.json");
+        let project = dir.path().join(".alphacentauri.config.json");
+        let project_str = project.to_string_lossy().to_string();
+        fs::write(&project, "{\"shell\": {\"program\": \"fish\"}}").unwrap();
+        let hash = content_hash(&[&project_str]);
+
+        assert!(!is_trusted(Some(&trust_file), &project_str, &hash));
+        trust_project(&trust_file, &project_str, &hash).unwrap();
+        assert!(is_trusted(Some(&trust_file), &project_str, &hash));
+        assert!(!is_trusted(None, &project_str, &hash));
+
+        fs::write(&project, "{\"shell\": {\"program\": \"./evil.sh\"}}").unwrap();
+        let changed = content_hash(&[&project_str]);
+        assert_ne!(changed, hash);
+        assert!(!is_trusted(Some(&trust_file), &project_str, &changed));
+
+        let _ = dir.close();
+    }
+}
@@ -0,0 +1,375 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::usr_conf::LaunchSettings;
+
+// Expands a leading ~, then $NAME, ${NAME} and ${NAME:-default} the way a POSIX shell would.
+// Unset variables expand to nothing, the default is used when a variable is unset or empty,
+// and $$ is a literal $
+pub fn expand<F: Fn(&str) -> Option<String>>(
+    value: &str,
+    lookup: &F,
+    home: Option<&Path>,
+) -> String {
+    let mut expanded = String::new();
+    let mut rest = value;
+
+    if let Some(home) = home {
+        let after_tilde = rest.strip_prefix('~');
+        if let Some(after_tilde) = after_tilde.filter(|after| {
+            after.is_empty()
+                || after.starts_with('/')
+                || after.starts_with(std::path::MAIN_SEPARATOR)
+        }) {
+            expanded.push_str(&home.to_string_lossy());
+            rest = after_tilde;
+        }
+    }
+
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+
+        if let Some(after) = rest.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+        } else if let Some(braced) = rest.strip_prefix('{') {
+            match closing_brace(braced) {
+                Some(end) => {
+                    expanded.push_str(&expand_braced(&braced[..end], lookup, home));
+                    rest = &braced[end + 1..];
+                }
+                None => {
+                    expanded.push_str("${");
+                    rest = braced;
+                }
+            }
+        } else {
+            let name_len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            if name_len == 0 || rest.starts_with(|c: char| c.is_ascii_digit()) {
+                expanded.push('$');
+            } else {
+                expanded.push_str(&lookup(&rest[..name_len]).unwrap_or_default());
+                rest = &rest[name_len..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+// the index of the } closing a ${, allowing ${A:-${B}} to nest
+fn closing_brace(braced: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (index, c) in braced.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(index),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn expand_braced<F: Fn(&str) -> Option<String>>(
+    inner: &str,
+    lookup: &F,
+    home: Option<&Path>,
+) -> String {
+    match inner.split_once(":-") {
+        Some((name, default)) => lookup(name)
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(|| expand(default, lookup, home)),
+        None => lookup(inner).unwrap_or_default(),
+    }
+}
+
+// Reads KEY=value lines, optionally prefixed with `export`. Values in single quotes are
+// taken literally, double quoted and unquoted values are expanded and may refer to keys set
+// on earlier lines. Lines starting with # are comments
+pub fn parse_env_file<F: Fn(&str) -> Option<String>>(
+    contents: &str,
+    lookup: &F,
+    home: Option<&Path>,
+) -> Result<Vec<(String, String)>, String> {
+    let mut values: Vec<(String, String)> = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let error = |message: &str| format!("line {}: {}", index + 1, message);
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error("expected KEY=value"))?;
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(error(&format!("{:?} is not a valid variable name", key)));
+        }
+
+        let earlier = |name: &str| {
+            values
+                .iter()
+                .rev()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+                .or_else(|| lookup(name))
+        };
+        let value = value.trim_start();
+        let value = if let Some(quoted) = value.strip_prefix('\'') {
+            let end = quoted
+                .find('\'')
+                .ok_or_else(|| error("missing closing '"))?;
+            quoted[..end].to_string()
+        } else if let Some(quoted) = value.strip_prefix('"') {
+            let unescaped =
+                unescape_double_quoted(quoted).ok_or_else(|| error("missing closing \""))?;
+            expand(&unescaped, &earlier, home)
+        } else {
+            // an unquoted value ends at a comment
+            let value = match value.find(" #") {
+                Some(comment) => &value[..comment],
+                None => value,
+            };
+            expand(value.trim_end(), &earlier, home)
+        };
+        values.push((key.to_string(), value));
+    }
+    Ok(values)
+}
+
+// the contents of a double quoted value up to its closing quote, an escaped \$ is kept as $$
+// so expand() leaves it as a literal $
+fn unescape_double_quoted(quoted: &str) -> Option<String> {
+    let mut unescaped = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(unescaped),
+            '\\' => match chars.next()? {
+                'n' => unescaped.push('\n'),
+                't' => unescaped.push('\t'),
+                '$' => unescaped.push_str("$$"),
+                other => unescaped.push(other),
+            },
+            other => unescaped.push(other),
+        }
+    }
+    None
+}
+
+// Expands the program, args, env values and cwd against the app's environment. The env file
+// is loaded first, env values can refer to it but not to each other, so PATH=$HOME/bin:$PATH
+// extends the PATH the app was started with
+pub fn expand_launch_settings(settings: LaunchSettings) -> Result<LaunchSettings, String> {
+    expand_launch_settings_with(
+        settings,
+        &|name| std::env::var(name).ok(),
+        dir::home_dir().as_deref(),
+    )
+}
+
+// What create_session launches: the caller's args replace the configured ones and its env is
+// merged over the configured env. Only the config's values are expanded, the caller's are
+// passed on as they are, e.g. a $VAR in a remote command is for the remote shell
+pub fn resolve_session_launch(
+    settings: LaunchSettings,
+    args: Option<Vec<String>>,
+    env: Option<HashMap<String, String>>,
+) -> Result<LaunchSettings, String> {
+    Ok(with_overrides(expand_launch_settings(settings)?, args, env))
+}
+
+fn with_overrides(
+    mut settings: LaunchSettings,
+    args: Option<Vec<String>>,
+    env: Option<HashMap<String, String>>,
+) -> LaunchSettings {
+    if let Some(args) = args {
+        settings.args = args;
+    }
+    settings.env.extend(env.unwrap_or_default());
+    settings
+}
+
+fn expand_launch_settings_with<F: Fn(&str) -> Option<String>>(
+    settings: LaunchSettings,
+    lookup: &F,
+    home: Option<&Path>,
+) -> Result<LaunchSettings, String> {
+    let mut env = HashMap::new();
+    if let Some(env_file) = &settings.env_file {
+        let path = PathBuf::from(expand(env_file, lookup, home));
+        let path = match home {
+            Some(home) if path.is_relative() => home.join(path),
+            _ => path,
+        };
+        let contents =
+            fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.to_string_lossy(), e))?;
+        env.extend(
+            parse_env_file(&contents, lookup, home)
+                .map_err(|e| format!("{}: {}", path.to_string_lossy(), e))?,
+        );
+    }
+
+    let lookup = |name: &str| env.get(name).cloned().or_else(|| lookup(name));
+    let expanded_env: Vec<(String, String)> = settings
+        .env
+        .iter()
+        .map(|(key, value)| (key.clone(), expand(value, &lookup, home)))
+        .collect();
+    let program = expand(&settings.program, &lookup, home);
+    let args = settings
+        .args
+        .iter()
+        .map(|arg| expand(arg, &lookup, home))
+        .collect();
+    let cwd = settings.cwd.as_ref().map(|cwd| expand(cwd, &lookup, home));
+    env.extend(expanded_env);
+
+    Ok(LaunchSettings {
+        program,
+        args,
+        env,
+        env_file: settings.env_file,
+        cwd,
+        title: settings.title,
+        font: settings.font,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some(String::from("/home/user")),
+            "PATH" => Some(String::from("/usr/bin")),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn expand_replaces_variables_and_home() {
+        let home = Some(Path::new("/home/user"));
+
+        assert_eq!(
+            expand("$HOME/bin:$PATH", &lookup, home),
+            "/home/user/bin:/usr/bin"
+        );
+        assert_eq!(expand("${HOME}x", &lookup, home), "/home/userx");
+        assert_eq!(expand("${MISSING:-/opt}", &lookup, home), "/opt");
+        assert_eq!(expand("${EMPTY:-${PATH}}", &lookup, home), "/usr/bin");
+        assert_eq!(expand("~/code", &lookup, home), "/home/user/code");
+        assert_eq!(
+            expand("a~b $$HOME $1 $MISSING.", &lookup, home),
+            "a~b $HOME $1 ."
+        );
+        assert_eq!(expand("${unterminated", &lookup, home), "${unterminated");
+    }
+
+    #[test]
+    fn parse_env_file_reads_dotenv_syntax() {
+        let contents = "# comment\nexport A=1\nB = \"$A two\\n\"\nC='$A literal'\nD=$B # trailing\nE=\"\\$A\"\n";
+
+        let actual = parse_env_file(contents, &lookup, None).unwrap();
+
+        assert_eq!(
+            actual,
+            Vec::from([
+                (String::from("A"), String::from("1")),
+                (String::from("B"), String::from("1 two\n")),
+                (String::from("C"), String::from("$A literal")),
+                (String::from("D"), String::from("1 two\n")),
+                (String::from("E"), String::from("$A")),
+            ])
+        );
+        assert!(parse_env_file("A=\"open", &lookup, None).is_err());
+        assert!(parse_env_file("not a pair", &lookup, None).is_err());
+    }
+
+    #[test]
+    fn expand_launch_settings_loads_env_file_beneath_env() {
+        let dir = TempDir::new("shell_env").unwrap();
+        fs::write(dir.path().join(".env"), "TOOLS=$HOME/tools\nEDITOR=vi\n").unwrap();
+        let settings = LaunchSettings {
+            program: String::from("~/bin/fish"),
+            args: Vec::from([String::from("--init=${TOOLS}")]),
+            env: HashMap::from([
+                (String::from("PATH"), String::from("$TOOLS:$PATH")),
+                (String::from("EDITOR"), String::from("nvim")),
+            ]),
+            env_file: Some(String::from(".env")),
+            cwd: Some(String::from("${PROJECTS:-~/src}")),
+            title: None,
+            font: None,
+        };
+
+        let actual = expand_launch_settings_with(settings, &lookup, Some(dir.path())).unwrap();
+
+        let home = dir.path().to_string_lossy();
+        assert_eq!(actual.program, format!("{}/bin/fish", home));
+        assert_eq!(
+            actual.args,
+            Vec::from([String::from("--init=/home/user/tools")])
+        );
+        assert_eq!(actual.env["PATH"], "/home/user/tools:/usr/bin");
+        assert_eq!(actual.env["EDITOR"], "nvim");
+        assert_eq!(actual.env["TOOLS"], "/home/user/tools");
+        assert_eq!(actual.cwd, Some(format!("{}/src", home)));
+
+        let missing = LaunchSettings {
+            env_file: Some(String::from("missing.env")),
+            ..actual
+        };
+        assert!(expand_launch_settings_with(missing, &lookup, Some(dir.path())).is_err());
+
+        let _ = dir.close();
+    }
+
+    #[test]
+    fn session_overrides_merge_over_the_resolved_env_unexpanded() {
+        let dir = TempDir::new("shell_env").unwrap();
+        fs::write(dir.path().join(".env"), "TOOLS=$HOME/tools\n").unwrap();
+        let settings = LaunchSettings {
+            program: String::from("fish"),
+            args: Vec::from([String::from("-l")]),
+            env: HashMap::from([
+                (String::from("PATH"), String::from("$TOOLS:$PATH")),
+                (String::from("EDITOR"), String::from("nvim")),
+            ]),
+            env_file: Some(String::from(".env")),
+            cwd: None,
+            title: None,
+            font: None,
+        };
+        let expanded = expand_launch_settings_with(settings, &lookup, Some(dir.path())).unwrap();
+
+        let actual = with_overrides(
+            expanded,
+            Some(Vec::from([String::from("--init=$TOOLS")])),
+            Some(HashMap::from([(
+                String::from("EDITOR"),
+                String::from("$TOOLS/hx"),
+            )])),
+        );
+
+        assert_eq!(actual.args, Vec::from([String::from("--init=$TOOLS")]));
+        assert_eq!(actual.env["PATH"], "/home/user/tools:/usr/bin");
+        assert_eq!(actual.env["EDITOR"], "$TOOLS/hx");
+        assert_eq!(actual.env["TOOLS"], "/home/user/tools");
+
+        let _ = dir.close();
+    }
+}
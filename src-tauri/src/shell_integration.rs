@@ -0,0 +1,277 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::detection::{strip_escape_sequences, OutputBuffer};
+use crate::osc::{percent_decode, OscSequence};
+
+// FinalTerm style semantic prompt marks, supported by most shell integration scripts
+pub const SEMANTIC_PROMPT_CODE: &str = "133";
+// VS Code shell integration, used here for the explicit command line property
+pub const VSCODE_CODE: &str = "633";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandEvent {
+    Started,
+    Finished(FinishedCommand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FinishedCommand {
+    pub command: String,
+    pub cwd: Option<String>,
+    pub exit_code: Option<i32>,
+    pub started_at: SystemTime,
+    pub duration: Duration,
+}
+
+#[derive(Debug)]
+struct RunningCommand {
+    command: String,
+    cwd: Option<String>,
+    started_at: SystemTime,
+    started: Instant,
+}
+
+#[derive(Debug, Default)]
+pub struct CommandTracker {
+    pending_command: Option<String>,
+    // output offset of the end of the B mark, where the command line the user types starts
+    input_start: Option<u64>,
+    running: Option<RunningCommand>,
+}
+
+impl CommandTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // `output` holds the session output up to and including the sequence
+    pub fn handle(
+        &mut self,
+        sequence: &OscSequence,
+        output: &OutputBuffer,
+    ) -> Option<CommandEvent> {
+        let mut parts = sequence.data.split(';');
+        let mark = parts.next().unwrap_or_default();
+
+        match (sequence.code.as_str(), mark) {
+            (VSCODE_CODE, "E") => {
+                self.pending_command = parts.next().map(unescape_vscode_command);
+                None
+            }
+            // a new prompt after one that was abandoned, e.g. with ctrl+c
+            (SEMANTIC_PROMPT_CODE, "A") | (VSCODE_CODE, "A") => {
+                self.input_start = None;
+                None
+            }
+            (SEMANTIC_PROMPT_CODE, "B") | (VSCODE_CODE, "B") => {
+                self.input_start = Some(sequence.end);
+                None
+            }
+            (SEMANTIC_PROMPT_CODE, "C") | (VSCODE_CODE, "C") => {
+                let input_start = self.input_start.take();
+                let command = parts
+                    .find_map(parse_cmdline_option)
+                    .or_else(|| self.pending_command.take())
+                    .or_else(|| {
+                        input_start.map(|start| typed_command(&output.range(start, sequence.start)))
+                    })
+                    .unwrap_or_default();
+                self.pending_command = None;
+                self.running = Some(RunningCommand {
+                    command,
+                    cwd: None,
+                    started_at: SystemTime::now(),
+                    started: Instant::now(),
+                });
+                Some(CommandEvent::Started)
+            }
+            (SEMANTIC_PROMPT_CODE, "D") | (VSCODE_CODE, "D") => {
+                let running = self.running.take()?;
+                let exit_code = parts.next().and_then(|code| code.trim().parse().ok());
+                Some(CommandEvent::Finished(FinishedCommand {
+                    command: running.command,
+                    cwd: running.cwd,
+                    exit_code,
+                    started_at: running.started_at,
+                    duration: running.started.elapsed(),
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn set_cwd(&mut self, cwd: Option<String>) {
+        if let Some(running) = self.running.as_mut() {
+            running.cwd = cwd;
+        }
+    }
+}
+
+// Plain FinalTerm marks carry no command line, but the shell echoes what the user typed between
+// the B and C marks
+fn typed_command(echoed: &[u8]) -> String {
+    let mut command = String::new();
+    // a backspace moves back over the character before it, which the text after it replaces
+    for (index, segment) in echoed.split(|byte| *byte == b'\x08').enumerate() {
+        if index > 0 {
+            command.pop();
+        }
+        command.push_str(&strip_escape_sequences(segment));
+    }
+    command.trim().to_string()
+}
+
+// kitty sends the command line as an option on the C mark, either raw or percent-encoded
+fn parse_cmdline_option(option: &str) -> Option<String> {
+    if let Some(cmdline) = option.strip_prefix("cmdline_url=") {
+        Some(percent_decode(cmdline))
+    } else {
+        option.strip_prefix("cmdline=").map(String::from)
+    }
+}
+
+// VS Code escapes backslashes and control characters (including ';') as \\ and \xAB. The
+// escapes are bytes, a non-ASCII character may be split across several of them
+fn unescape_vscode_command(raw: &str) -> String {
+    let mut result = Vec::with_capacity(raw.len());
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            result.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        match chars.peek() {
+            Some('\\') => {
+                chars.next();
+                result.push(b'\\');
+            }
+            Some('x') => {
+                chars.next();
+                let hex: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(byte) => result.push(byte),
+                    Err(_) => {
+                        result.extend_from_slice(b"\\x");
+                        result.extend_from_slice(hex.as_bytes());
+                    }
+                }
+            }
+            _ => result.push(b'\\'),
+        }
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence(code: &str, data: &str) -> OscSequence {
+        OscSequence {
+            code: code.to_string(),
+            data: data.to_string(),
+            start: 0,
+            end: 0,
+        }
+    }
+
+    // Appends the sequence to the output like a shell would, so its offsets point into it
+    fn emit(output: &mut OutputBuffer, code: &str, data: &str) -> OscSequence {
+        let start = output.end_offset();
+        output.push(format!("\x1b]{};{}\x07", code, data).as_bytes());
+        OscSequence {
+            end: output.end_offset(),
+            start,
+            ..sequence(code, data)
+        }
+    }
+
+    #[test]
+    fn handle_reports_finished_command_with_exit_code() {
+        let mut tracker = CommandTracker::new();
+        let output = OutputBuffer::new();
+
+        assert_eq!(tracker.handle(&sequence("133", "A"), &output), None);
+        assert_eq!(
+            tracker.handle(&sequence("633", "E;ls -la\\x3b echo done"), &output),
+            None
+        );
+        assert_eq!(
+            tracker.handle(&sequence("133", "C"), &output),
+            Some(CommandEvent::Started)
+        );
+        tracker.set_cwd(Some("/home/user".to_string()));
+
+        match tracker.handle(&sequence("133", "D;2"), &output) {
+            Some(CommandEvent::Finished(finished)) => {
+                assert_eq!(finished.command, "ls -la; echo done");
+                assert_eq!(finished.cwd, Some("/home/user".to_string()));
+                assert_eq!(finished.exit_code, Some(2));
+            }
+            other => panic!("Expected finished command, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unescape_vscode_command_decodes_multibyte_characters() {
+        assert_eq!(
+            unescape_vscode_command("echo caf\\xc3\\xa9 \\\\ ü"),
+            "echo café \\ ü"
+        );
+        assert_eq!(unescape_vscode_command("a\\xzz"), "a\\xzz");
+    }
+
+    #[test]
+    fn handle_reads_kitty_cmdline_url() {
+        let mut tracker = CommandTracker::new();
+        let output = OutputBuffer::new();
+
+        tracker.handle(
+            &sequence("133", "C;cmdline_url=git%20commit%20-m%20%22a+b%22"),
+            &output,
+        );
+
+        match tracker.handle(&sequence("133", "D"), &output) {
+            Some(CommandEvent::Finished(finished)) => {
+                assert_eq!(finished.command, "git commit -m \"a+b\"");
+                assert_eq!(finished.exit_code, None);
+            }
+            other => panic!("Expected finished command, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn handle_reads_command_typed_between_plain_marks() {
+        let mut tracker = CommandTracker::new();
+        let mut output = OutputBuffer::new();
+
+        let prompt = emit(&mut output, "133", "A");
+        tracker.handle(&prompt, &output);
+        output.push(b"~/src $ ");
+        let input = emit(&mut output, "133", "B");
+        tracker.handle(&input, &output);
+        // typed "carg", erased the "g" and finished the word, then pressed enter
+        output.push(b"carg\x08\x1b[Kgo \x1b[1mtest\x1b[0m\r\n");
+        let start = emit(&mut output, "133", "C");
+        tracker.handle(&start, &output);
+        output.push(b"running 3 tests\r\n");
+        let finish = emit(&mut output, "133", "D;0");
+
+        match tracker.handle(&finish, &output) {
+            Some(CommandEvent::Finished(finished)) => {
+                assert_eq!(finished.command, "cargo test");
+                assert_eq!(finished.exit_code, Some(0));
+            }
+            other => panic!("Expected finished command, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn handle_ignores_finish_without_start() {
+        let mut tracker = CommandTracker::new();
+        let output = OutputBuffer::new();
+
+        assert_eq!(tracker.handle(&sequence("133", "D;0"), &output), None);
+    }
+}
@@ -0,0 +1,175 @@
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+#[cfg(not(target_os = "windows"))]
+const CANDIDATE_SHELLS: &[&str] = &[
+    "bash", "zsh", "fish", "sh", "dash", "ksh", "tcsh", "nu", "pwsh", "xonsh", "elvish",
+];
+
+#[cfg(target_os = "windows")]
+const CANDIDATE_SHELLS: &[&str] = &[
+    "pwsh.exe",
+    "powershell.exe",
+    "cmd.exe",
+    "bash.exe",
+    "nu.exe",
+    "xonsh.exe",
+    "elvish.exe",
+];
+
+// some shells will happily start an interactive session when they don't understand --version
+const VERSION_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DetectedShell {
+    pub name: String,
+    pub path: String,
+    pub version: Option<String>,
+    // matches $SHELL, the shell used when no program is configured
+    pub is_default: bool,
+}
+
+pub fn parse_etc_shells(content: &str) -> Vec<PathBuf> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(PathBuf::from)
+        .collect()
+}
+
+pub fn find_in_path(name: &str, path_var: &OsStr) -> Option<PathBuf> {
+    std::env::split_paths(path_var)
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+fn shell_version(path: &Path) -> Option<String> {
+    // cmd.exe has no version flag and would just open a prompt
+    if path
+        .file_name()
+        .is_some_and(|name| name.eq_ignore_ascii_case("cmd.exe"))
+    {
+        return None;
+    }
+
+    let mut child = Command::new(path)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let stdout = child.stdout.take()?;
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let output = std::io::read_to_string(stdout).unwrap_or_default();
+        let _ = sender.send(output);
+    });
+
+    match receiver.recv_timeout(VERSION_TIMEOUT) {
+        Ok(output) => {
+            let _ = child.wait();
+            output
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .map(String::from)
+        }
+        Err(_) => {
+            #[cfg(debug_assertions)]
+            println!("Timed out getting the version of {:?}", path);
+            let _ = child.kill();
+            let _ = child.wait();
+            None
+        }
+    }
+}
+
+pub fn discover_shells() -> Vec<DetectedShell> {
+    let mut candidates: Vec<PathBuf> = Vec::new();
+
+    let default_shell = std::env::var_os("SHELL").map(PathBuf::from);
+    if let Some(shell) = &default_shell {
+        candidates.push(shell.clone());
+    }
+    if let Ok(content) = std::fs::read_to_string("/etc/shells") {
+        candidates.extend(parse_etc_shells(&content));
+    }
+    if let Some(path_var) = std::env::var_os("PATH") {
+        candidates.extend(
+            CANDIDATE_SHELLS
+                .iter()
+                .filter_map(|name| find_in_path(name, &path_var)),
+        );
+    }
+
+    let default_shell = default_shell.and_then(|shell| std::fs::canonicalize(shell).ok());
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    let mut shells = Vec::new();
+    for candidate in candidates {
+        if !candidate.is_file() {
+            continue;
+        }
+        // /bin and /usr/bin are often the same directory, and sh is often a link to another shell
+        let canonical = std::fs::canonicalize(&candidate).unwrap_or(candidate.clone());
+        if !seen.insert(canonical.clone()) {
+            continue;
+        }
+        let name = candidate
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        shells.push(DetectedShell {
+            name,
+            path: candidate.to_string_lossy().to_string(),
+            version: shell_version(&candidate),
+            is_default: default_shell.as_ref() == Some(&canonical),
+        });
+    }
+
+    shells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn parse_etc_shells_skips_comments() {
+        let content = "# /etc/shells: valid login shells\n/bin/sh\n\n/usr/bin/zsh\n  # trailing\n";
+
+        let actual = parse_etc_shells(content);
+
+        assert_eq!(
+            actual,
+            Vec::from([PathBuf::from("/bin/sh"), PathBuf::from("/usr/bin/zsh")])
+        );
+    }
+
+    #[test]
+    fn find_in_path_searches_each_directory() {
+        let first = TempDir::new("path_first").unwrap();
+        let second = TempDir::new("path_second").unwrap();
+        let expected = second.path().join("nu");
+        std::fs::write(&expected, "").unwrap();
+        let path_var = std::env::join_paths([first.path(), second.path()]).unwrap();
+
+        assert_eq!(find_in_path("nu", &path_var), Some(expected));
+        assert_eq!(find_in_path("xonsh", &path_var), None);
+
+        let _ = first.close();
+        let _ = second.close();
+    }
+}
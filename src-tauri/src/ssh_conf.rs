@@ -0,0 +1,316 @@
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+use crate::usr_conf::SshHostSettings;
+
+// profiles named "ssh:<host>" launch `ssh <host>` instead of the configured shell
+pub const SSH_PROFILE_PREFIX: &str = "ssh:";
+
+const MAX_INCLUDE_DEPTH: u32 = 16;
+const MAX_RECENT_HOSTS: usize = 10;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SshHostProfile {
+    pub name: String,
+    pub host: String,
+    pub title: String,
+    pub color: Option<String>,
+    pub recently_used: bool,
+}
+
+// `*` matches any run of characters and `?` exactly one, like ssh_config(5) patterns
+pub fn matches_pattern(pattern: &str, host: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let host: Vec<char> = host.to_lowercase().chars().collect();
+    let (mut p, mut h) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while h < host.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == host[h]) {
+            p += 1;
+            h += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, h));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            h = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn split_arguments(line: &str) -> Vec<String> {
+    let mut arguments = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    arguments.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        arguments.push(current);
+    }
+    arguments
+}
+
+// keywords may be separated from their arguments by whitespace or a single '='
+fn split_keyword(line: &str) -> Option<(String, &str)> {
+    let line = line.trim();
+    let end = line.find(|c: char| c.is_whitespace() || c == '=')?;
+    let keyword = line[..end].to_lowercase();
+    let rest = line[end..].trim_start();
+    let rest = rest.strip_prefix('=').unwrap_or(rest).trim_start();
+    Some((keyword, rest))
+}
+
+fn expand_include(pattern: &str, ssh_dir: &Path) -> Vec<PathBuf> {
+    let pattern = if let Some(rest) = pattern.strip_prefix("~/") {
+        match dir::home_dir() {
+            Some(home) => home.join(rest),
+            None => return Vec::new(),
+        }
+    } else {
+        let path = PathBuf::from(pattern);
+        if path.is_absolute() {
+            path
+        } else {
+            // relative includes in a user config are relative to ~/.ssh
+            ssh_dir.join(path)
+        }
+    };
+
+    match glob::glob(&pattern.to_string_lossy()) {
+        Ok(paths) => {
+            let mut paths: Vec<PathBuf> = paths.filter_map(|p| p.ok()).collect();
+            paths.sort();
+            paths
+        }
+        Err(_) => Vec::new(),
+    }
+}
+
+fn collect_hosts(path: &Path, ssh_dir: &Path, depth: u32, hosts: &mut BTreeSet<String>) {
+    if depth > MAX_INCLUDE_DEPTH {
+        #[cfg(debug_assertions)]
+        println!("Include depth exceeded at {:?}", path);
+        return;
+    }
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return;
+    };
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((keyword, rest)) = split_keyword(line) else {
+            continue;
+        };
+        match keyword.as_str() {
+            "include" => {
+                for pattern in split_arguments(rest) {
+                    for included in expand_include(&pattern, ssh_dir) {
+                        collect_hosts(&included, ssh_dir, depth + 1, hosts);
+                    }
+                }
+            }
+            "host" => {
+                let patterns = split_arguments(rest);
+                let exclusions: Vec<&str> = patterns
+                    .iter()
+                    .filter_map(|p| p.strip_prefix('!'))
+                    .collect();
+                for pattern in patterns.iter() {
+                    let is_concrete = !pattern.starts_with('!')
+                        && !pattern.contains('*')
+                        && !pattern.contains('?');
+                    if is_concrete && !exclusions.iter().any(|e| matches_pattern(e, pattern)) {
+                        hosts.insert(pattern.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Concrete host names from an ssh config file, following Include directives
+pub fn parse_ssh_config(path: &Path) -> Vec<String> {
+    let ssh_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut hosts = BTreeSet::new();
+    collect_hosts(path, &ssh_dir, 0, &mut hosts);
+    hosts.into_iter().collect()
+}
+
+pub fn user_ssh_config_path() -> Option<PathBuf> {
+    dir::home_dir().map(|home| home.join(".ssh").join("config"))
+}
+
+pub fn host_profiles(
+    hosts: Vec<String>,
+    settings: &HashMap<String, SshHostSettings>,
+    recent_hosts: &[String],
+) -> Vec<SshHostProfile> {
+    let mut profiles: Vec<SshHostProfile> = hosts
+        .into_iter()
+        .map(|host| {
+            let host_settings = settings.get(&host);
+            SshHostProfile {
+                name: format!("{}{}", SSH_PROFILE_PREFIX, host),
+                title: host_settings
+                    .and_then(|s| s.title.clone())
+                    .unwrap_or(host.clone()),
+                color: host_settings.and_then(|s| s.color.clone()),
+                recently_used: recent_hosts.contains(&host),
+                host,
+            }
+        })
+        .collect();
+
+    // most recently used first, then alphabetical
+    profiles.sort_by_key(|profile| {
+        (
+            recent_hosts
+                .iter()
+                .position(|h| *h == profile.host)
+                .unwrap_or(usize::MAX),
+            profile.host.clone(),
+        )
+    });
+    profiles
+}
+
+// ssh would treat a leading '-' as an option, e.g. -oProxyCommand
+pub fn is_valid_host(host: &str) -> bool {
+    !host.is_empty() && !host.starts_with('-') && !host.contains(char::is_whitespace)
+}
+
+// Only hosts that are offered as profiles, anything else did not come from the host list
+pub fn is_known_host(host: &str, config_hosts: &[String], recent_hosts: &[String]) -> bool {
+    is_valid_host(host) && config_hosts.iter().chain(recent_hosts).any(|h| h == host)
+}
+
+pub fn mark_recent(recent_hosts: &mut Vec<String>, host: &str) {
+    recent_hosts.retain(|h| h != host);
+    recent_hosts.insert(0, host.to_string());
+    recent_hosts.truncate(MAX_RECENT_HOSTS);
+}
+
+pub fn load_recent_hosts(file_loc: &Path) -> Vec<String> {
+    std::fs::read_to_string(file_loc)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_recent_hosts(file_loc: &Path, recent_hosts: &[String]) -> std::io::Result<()> {
+    if let Some(parent) = file_loc.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string(recent_hosts)?;
+    std::fs::write(file_loc, json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn matches_pattern_supports_wildcards() {
+        assert!(matches_pattern("*.example.com", "db.example.com"));
+        assert!(matches_pattern("web-??", "web-01"));
+        assert!(!matches_pattern("web-??", "web-001"));
+        assert!(matches_pattern("*", "anything"));
+    }
+
+    #[test]
+    fn parse_ssh_config_follows_includes_and_exclusions() {
+        let dir = TempDir::new("ssh_dir").unwrap();
+        std::fs::create_dir(dir.path().join("config.d")).unwrap();
+        std::fs::write(
+            dir.path().join("config"),
+            "Include config.d/*\n\nHost bastion web-01 web-02 !web-02 *.internal\n  User admin\n\nHost *\n  ServerAliveInterval 30\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("config.d").join("work"),
+            "Host=build \"ci-runner\"\nMatch host foo\n",
+        )
+        .unwrap();
+
+        let actual = parse_ssh_config(&dir.path().join("config"));
+
+        assert_eq!(
+            actual,
+            Vec::from([
+                "bastion".to_string(),
+                "build".to_string(),
+                "ci-runner".to_string(),
+                "web-01".to_string(),
+            ])
+        );
+
+        let _ = dir.close();
+    }
+
+    #[test]
+    fn host_profiles_orders_recent_hosts_first() {
+        let hosts = Vec::from(["alpha".to_string(), "beta".to_string(), "gamma".to_string()]);
+        let settings = HashMap::from([(
+            "gamma".to_string(),
+            SshHostSettings {
+                title: Some("Gamma prod".to_string()),
+                color: Some("#ff0000".to_string()),
+            },
+        )]);
+        let mut recent = Vec::new();
+        mark_recent(&mut recent, "beta");
+        mark_recent(&mut recent, "gamma");
+
+        let actual = host_profiles(hosts, &settings, &recent);
+
+        assert_eq!(
+            actual.iter().map(|p| p.host.as_str()).collect::<Vec<_>>(),
+            Vec::from(["gamma", "beta", "alpha"])
+        );
+        assert_eq!(actual[0].name, "ssh:gamma");
+        assert_eq!(actual[0].title, "Gamma prod");
+        assert!(!actual[2].recently_used);
+    }
+
+    #[test]
+    fn is_known_host_accepts_configured_and_recent_hosts_only() {
+        let config_hosts = Vec::from(["bastion".to_string(), "-oProxyCommand=x".to_string()]);
+        let recent_hosts = Vec::from(["old-box".to_string()]);
+
+        assert!(is_known_host("bastion", &config_hosts, &recent_hosts));
+        assert!(is_known_host("old-box", &config_hosts, &recent_hosts));
+        assert!(!is_known_host(
+            "evil.example.com",
+            &config_hosts,
+            &recent_hosts
+        ));
+        assert!(!is_known_host(
+            "-oProxyCommand=x",
+            &config_hosts,
+            &recent_hosts
+        ));
+    }
+}
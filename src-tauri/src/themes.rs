@@ -0,0 +1,633 @@
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{config_format::ConfigFormat, config_layers};
+
+// The 16 ANSI colors, named like xterm.js names them. Unset colors come from the theme, then
+// the default palette
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Palette {
+    pub black: Option<String>,
+    pub red: Option<String>,
+    pub green: Option<String>,
+    pub yellow: Option<String>,
+    pub blue: Option<String>,
+    pub magenta: Option<String>,
+    pub cyan: Option<String>,
+    pub white: Option<String>,
+    pub bright_black: Option<String>,
+    pub bright_red: Option<String>,
+    pub bright_green: Option<String>,
+    pub bright_yellow: Option<String>,
+    pub bright_blue: Option<String>,
+    pub bright_magenta: Option<String>,
+    pub bright_cyan: Option<String>,
+    pub bright_white: Option<String>,
+}
+
+impl Palette {
+    // in ANSI order, color 0 (black) to 15 (bright white)
+    fn colors_mut(&mut self) -> [&mut Option<String>; 16] {
+        [
+            &mut self.black,
+            &mut self.red,
+            &mut self.green,
+            &mut self.yellow,
+            &mut self.blue,
+            &mut self.magenta,
+            &mut self.cyan,
+            &mut self.white,
+            &mut self.bright_black,
+            &mut self.bright_red,
+            &mut self.bright_green,
+            &mut self.bright_yellow,
+            &mut self.bright_blue,
+            &mut self.bright_magenta,
+            &mut self.bright_cyan,
+            &mut self.bright_white,
+        ]
+    }
+}
+
+const PALETTE_NAMES: [&str; 16] = [
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "brightBlack",
+    "brightRed",
+    "brightGreen",
+    "brightYellow",
+    "brightBlue",
+    "brightMagenta",
+    "brightCyan",
+    "brightWhite",
+];
+
+// The colors section of the config. A theme file holds the same settings, apart from theme.
+// Colors are written as #rgb, #rrggbb or #rrggbbaa
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Colors {
+    // the name of a file in the themes directory, e.g. "dracula" for themes/dracula.json.
+    // Colors set here are applied over the theme
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme: Option<String>,
+    pub foreground: Option<String>,
+    pub background: Option<String>,
+    pub cursor: Option<String>,
+    pub selection: Option<String>,
+    pub palette: Palette,
+}
+
+impl Colors {
+    // every color with the name it has in the config, e.g. palette.brightRed
+    fn colors_mut(&mut self) -> Vec<(String, &mut Option<String>)> {
+        let mut colors = Vec::from([
+            (String::from("foreground"), &mut self.foreground),
+            (String::from("background"), &mut self.background),
+            (String::from("cursor"), &mut self.cursor),
+            (String::from("selection"), &mut self.selection),
+        ]);
+        colors.extend(
+            PALETTE_NAMES
+                .iter()
+                .map(|name| format!("palette.{}", name))
+                .zip(self.palette.colors_mut()),
+        );
+        colors
+    }
+
+    // sets each color that is set in overlay, returning the colors that aren't valid
+    fn apply(&mut self, mut overlay: Colors) -> Vec<String> {
+        let mut problems = Vec::new();
+        for ((name, color), (_, overlay_color)) in
+            self.colors_mut().into_iter().zip(overlay.colors_mut())
+        {
+            let Some(value) = overlay_color.take() else {
+                continue;
+            };
+            match normalize_color(&value) {
+                Ok(value) => *color = Some(value),
+                Err(e) => problems.push(format!("{}: {}", name, e)),
+            }
+        }
+        problems
+    }
+}
+
+// xterm.js's defaults, with the background the app has always used
+fn default_colors() -> Colors {
+    let color = |hex: &str| Some(String::from(hex));
+    Colors {
+        theme: None,
+        foreground: color("#ffffff"),
+        background: color("#020617"),
+        cursor: color("#ffffff"),
+        selection: color("#334155"),
+        palette: Palette {
+            black: color("#2e3436"),
+            red: color("#cc0000"),
+            green: color("#4e9a06"),
+            yellow: color("#c4a000"),
+            blue: color("#3465a4"),
+            magenta: color("#75507b"),
+            cyan: color("#06989a"),
+            white: color("#d3d7cf"),
+            bright_black: color("#555753"),
+            bright_red: color("#ef2929"),
+            bright_green: color("#8ae234"),
+            bright_yellow: color("#fce94f"),
+            bright_blue: color("#729fcf"),
+            bright_magenta: color("#ad7fa8"),
+            bright_cyan: color("#34e2e2"),
+            bright_white: color("#eeeeec"),
+        },
+    }
+}
+
+// Lower case #rrggbb, or #rrggbbaa when the color has an alpha. Alacritty's 0xrrggbb is
+// accepted too
+pub fn normalize_color(color: &str) -> Result<String, String> {
+    let trimmed = color.trim();
+    let hex = trimmed
+        .strip_prefix('#')
+        .or_else(|| trimmed.strip_prefix("0x"))
+        .filter(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| format!("{:?} is not a color, expected #rrggbb", color))?;
+    let hex = hex.to_lowercase();
+    match hex.len() {
+        3 => Ok(hex.chars().fold(String::from("#"), |mut expanded, c| {
+            expanded.push(c);
+            expanded.push(c);
+            expanded
+        })),
+        6 | 8 => Ok(format!("#{}", hex)),
+        _ => Err(format!("{:?} is not a color, expected #rrggbb", color)),
+    }
+}
+
+// Reads a theme file from the themes directory, as JSON, TOML or YAML
+pub fn load_theme(themes_dir: &Path, name: &str) -> Result<Colors, String> {
+    let path = config_layers::find_config_file(themes_dir, name).ok_or_else(|| {
+        format!(
+            "the theme {:?} was not found in {}",
+            name,
+            themes_dir.to_string_lossy()
+        )
+    })?;
+    let file_loc = path.to_string_lossy();
+    let contents = fs::read_to_string(&path).map_err(|e| format!("{}: {}", file_loc, e))?;
+    let value = ConfigFormat::from_path(&file_loc)
+        .parse(&contents)
+        .map_err(|diagnostics| {
+            let details: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+            format!("{}: {}", file_loc, details.join(", "))
+        })?;
+    serde_json::from_value(value).map_err(|e| format!("{}: {}", file_loc, e))
+}
+
+// The default colors, then the theme, then the colors set in the config. Every color in the
+// result is set. Problems are reported for a theme that can't be loaded and invalid colors,
+// which are skipped
+pub fn resolve_colors(colors: &Colors, themes_dir: Option<&Path>) -> (Colors, Vec<String>) {
+    let mut resolved = default_colors();
+    let mut problems = Vec::new();
+
+    if let Some(theme) = &colors.theme {
+        let loaded = match themes_dir {
+            Some(themes_dir) => load_theme(themes_dir, theme),
+            None => Err(String::from("the themes directory could not be found")),
+        };
+        match loaded {
+            Ok(theme_colors) => {
+                problems.extend(
+                    resolved
+                        .apply(theme_colors)
+                        .into_iter()
+                        .map(|problem| format!("theme {}, {}", theme, problem)),
+                );
+                resolved.theme = Some(theme.clone());
+            }
+            Err(e) => problems.push(format!("theme: {}", e)),
+        }
+    }
+
+    problems.extend(resolved.apply(Colors {
+        theme: None,
+        ..colors.clone()
+    }));
+    (resolved, problems)
+}
+
+// The theme formats of other terminals that can be imported
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThemeFormat {
+    ITerm,
+    Alacritty,
+    WindowsTerminal,
+    Base16,
+}
+
+impl ThemeFormat {
+    pub fn from_path(path: &Path) -> Option<ThemeFormat> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("itermcolors") => Some(ThemeFormat::ITerm),
+            Some("toml") => Some(ThemeFormat::Alacritty),
+            Some("json") => Some(ThemeFormat::WindowsTerminal),
+            Some("yaml") | Some("yml") => Some(ThemeFormat::Base16),
+            _ => None,
+        }
+    }
+
+    // The theme's colors and its name, when the file has one
+    pub fn import(self, contents: &str) -> Result<(Option<String>, Colors), String> {
+        match self {
+            ThemeFormat::ITerm => import_iterm(contents).map(|colors| (None, colors)),
+            ThemeFormat::Alacritty => import_alacritty(contents).map(|colors| (None, colors)),
+            ThemeFormat::WindowsTerminal => import_windows_terminal(contents),
+            ThemeFormat::Base16 => import_base16(contents),
+        }
+    }
+}
+
+// colors that aren't hex, such as Alacritty's CellForeground, are left for the defaults
+fn color_value(value: Option<&Value>) -> Option<String> {
+    value
+        .and_then(Value::as_str)
+        .and_then(|color| normalize_color(color).ok())
+}
+
+// An XML property list with a dictionary of red, green and blue components from 0 to 1 for
+// each color, e.g. <key>Ansi 1 Color</key><dict>...</dict>
+fn import_iterm(contents: &str) -> Result<Colors, String> {
+    let entry = Regex::new(r"(?s)<key>([^<]+)</key>\s*<dict>(.*?)</dict>").unwrap();
+    let component = Regex::new(
+        r"<key>(Red|Green|Blue) Component</key>\s*<(?:real|integer)>([^<]+)</(?:real|integer)>",
+    )
+    .unwrap();
+
+    let mut colors = Colors::default();
+    let mut found = false;
+    for captures in entry.captures_iter(contents) {
+        let mut rgb = [0u8; 3];
+        for component in component.captures_iter(&captures[2]) {
+            let index = match &component[1] {
+                "Red" => 0,
+                "Green" => 1,
+                _ => 2,
+            };
+            let value: f64 = component[2]
+                .trim()
+                .parse()
+                .map_err(|_| format!("{:?} is not a color component", &component[2]))?;
+            rgb[index] = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+        let color = Some(format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2]));
+
+        let name = captures[1].trim();
+        let slot = match name {
+            "Foreground Color" => &mut colors.foreground,
+            "Background Color" => &mut colors.background,
+            "Cursor Color" => &mut colors.cursor,
+            "Selection Color" => &mut colors.selection,
+            _ => {
+                let index = name
+                    .strip_prefix("Ansi ")
+                    .and_then(|name| name.strip_suffix(" Color"))
+                    .and_then(|index| index.parse::<usize>().ok());
+                match index.and_then(|index| colors.palette.colors_mut().into_iter().nth(index)) {
+                    Some(slot) => slot,
+                    None => continue,
+                }
+            }
+        };
+        *slot = color;
+        found = true;
+    }
+    if !found {
+        return Err(String::from("no colors were found in the property list"));
+    }
+    Ok(colors)
+}
+
+// [colors.primary], [colors.normal], [colors.bright], [colors.cursor] and [colors.selection]
+fn import_alacritty(contents: &str) -> Result<Colors, String> {
+    let value: Value = toml::from_str(contents).map_err(|e| e.message().to_string())?;
+    let sections = value
+        .get("colors")
+        .ok_or_else(|| String::from("there is no [colors] table"))?;
+    let color = |section: &str, name: &str| color_value(sections.get(section)?.get(name));
+
+    let mut colors = Colors {
+        foreground: color("primary", "foreground"),
+        background: color("primary", "background"),
+        cursor: color("cursor", "cursor"),
+        selection: color("selection", "background"),
+        ..Colors::default()
+    };
+    let names = [
+        "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+    ];
+    let palette_colors = names
+        .iter()
+        .map(|name| color("normal", name))
+        .chain(names.iter().map(|name| color("bright", name)));
+    for (slot, color) in colors.palette.colors_mut().into_iter().zip(palette_colors) {
+        *slot = color;
+    }
+    Ok(colors)
+}
+
+// A scheme from the "schemes" list of settings.json, which calls magenta purple
+fn import_windows_terminal(contents: &str) -> Result<(Option<String>, Colors), String> {
+    let value: Value = serde_json::from_str(contents).map_err(|e| e.to_string())?;
+    if value.get("schemes").is_some() {
+        return Err(String::from(
+            "this is a settings file, copy one of its schemes to a file of its own",
+        ));
+    }
+    let color = |name: &str| color_value(value.get(name));
+
+    let mut colors = Colors {
+        foreground: color("foreground"),
+        background: color("background"),
+        cursor: color("cursorColor"),
+        selection: color("selectionBackground"),
+        ..Colors::default()
+    };
+    let names = [
+        "black", "red", "green", "yellow", "blue", "purple", "cyan", "white",
+    ];
+    let palette_colors = names
+        .iter()
+        .map(|name| color(name))
+        .chain(names.iter().map(|name| {
+            let mut bright = format!("bright{}", name);
+            bright[6..7].make_ascii_uppercase();
+            color(&bright)
+        }));
+    for (slot, color) in colors.palette.colors_mut().into_iter().zip(palette_colors) {
+        *slot = color;
+    }
+    let name = value.get("name").and_then(Value::as_str).map(String::from);
+    Ok((name, colors))
+}
+
+// base00 to base0F, at the top level or under palette in the newer format. The ANSI colors
+// are mapped the way base16-shell maps them
+fn import_base16(contents: &str) -> Result<(Option<String>, Colors), String> {
+    let value: Value = serde_yaml::from_str(contents).map_err(|e| e.to_string())?;
+    let base = value.get("palette").unwrap_or(&value);
+    let color = |index: usize| {
+        let hex = base.get(format!("base{:02X}", index))?.as_str()?;
+        if hex.starts_with('#') {
+            normalize_color(hex).ok()
+        } else {
+            normalize_color(&format!("#{}", hex)).ok()
+        }
+    };
+    if (0..16).all(|index| color(index).is_none()) {
+        return Err(String::from("no base00 to base0F colors were found"));
+    }
+
+    let mut colors = Colors {
+        foreground: color(0x05),
+        background: color(0x00),
+        cursor: color(0x05),
+        selection: color(0x02),
+        ..Colors::default()
+    };
+    let ansi = [
+        0x00, 0x08, 0x0B, 0x0A, 0x0D, 0x0E, 0x0C, 0x05, 0x03, 0x08, 0x0B, 0x0A, 0x0D, 0x0E, 0x0C,
+        0x07,
+    ];
+    for (slot, index) in colors.palette.colors_mut().into_iter().zip(ansi) {
+        *slot = color(index);
+    }
+    let name = ["scheme", "name"]
+        .iter()
+        .find_map(|key| value.get(key).and_then(Value::as_str))
+        .map(String::from);
+    Ok((name, colors))
+}
+
+// A theme name that is safe to use as a file name, e.g. "Tokyo Night" becomes "tokyo-night"
+fn theme_file_stem(name: &str) -> String {
+    let stem: String = name
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect();
+    stem.split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-")
+}
+
+// Converts a theme from another terminal and saves it to the themes directory as JSON,
+// replacing a theme of the same name. Returns the name to use for colors.theme
+pub fn import_theme_file(path: &Path, themes_dir: &Path) -> Result<String, String> {
+    let file_loc = path.to_string_lossy();
+    let format = ThemeFormat::from_path(path).ok_or_else(|| {
+        format!(
+            "{}: expected an .itermcolors, Alacritty .toml, Windows Terminal .json or base16 .yaml file",
+            file_loc
+        )
+    })?;
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", file_loc, e))?;
+    let (name, colors) = format
+        .import(&contents)
+        .map_err(|e| format!("{}: {}", file_loc, e))?;
+
+    let file_stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let stem = theme_file_stem(name.as_deref().unwrap_or(&file_stem));
+    if stem.is_empty() {
+        return Err(format!("{}: the theme has no usable name", file_loc));
+    }
+
+    fs::create_dir_all(themes_dir)
+        .map_err(|e| format!("{}: {}", themes_dir.to_string_lossy(), e))?;
+    let theme_path: PathBuf = themes_dir.join(format!("{}.json", stem));
+    let json = serde_json::to_string_pretty(&colors).map_err(|e| e.to_string())?;
+    fs::write(&theme_path, json).map_err(|e| format!("{}: {}", theme_path.to_string_lossy(), e))?;
+    Ok(stem)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn normalize_color_accepts_hex_forms() {
+        assert_eq!(normalize_color("#ABC").unwrap(), "#aabbcc");
+        assert_eq!(normalize_color(" #1E1E2E ").unwrap(), "#1e1e2e");
+        assert_eq!(normalize_color("0x282a36").unwrap(), "#282a36");
+        assert_eq!(normalize_color("#28282a80").unwrap(), "#28282a80");
+        assert!(normalize_color("red").is_err());
+        assert!(normalize_color("#12345").is_err());
+    }
+
+    #[test]
+    fn resolve_colors_layers_config_over_theme_over_defaults() {
+        let dir = TempDir::new("themes").unwrap();
+        fs::write(
+            dir.path().join("night.toml"),
+            "background = \"#000000\"\nforeground = \"#cccccc\"\n[palette]\nred = \"#ff5555\"\n",
+        )
+        .unwrap();
+        let colors = Colors {
+            theme: Some(String::from("night")),
+            foreground: Some(String::from("#FFF")),
+            cursor: Some(String::from("not a color")),
+            ..Colors::default()
+        };
+
+        let (actual, problems) = resolve_colors(&colors, Some(dir.path()));
+
+        assert_eq!(actual.theme, Some(String::from("night")));
+        assert_eq!(actual.background, Some(String::from("#000000")));
+        assert_eq!(actual.foreground, Some(String::from("#ffffff")));
+        assert_eq!(actual.cursor, default_colors().cursor);
+        assert_eq!(actual.palette.red, Some(String::from("#ff5555")));
+        assert_eq!(actual.palette.blue, default_colors().palette.blue);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("cursor:"));
+
+        let missing = Colors {
+            theme: Some(String::from("missing")),
+            ..Colors::default()
+        };
+        let (actual, problems) = resolve_colors(&missing, Some(dir.path()));
+        assert_eq!(actual, default_colors());
+        assert_eq!(problems.len(), 1);
+
+        let _ = dir.close();
+    }
+
+    #[test]
+    fn import_iterm_reads_color_components() {
+        let contents = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+	<key>Ansi 1 Color</key>
+	<dict>
+		<key>Blue Component</key>
+		<real>0.0</real>
+		<key>Color Space</key>
+		<string>sRGB</string>
+		<key>Green Component</key>
+		<real>0.5</real>
+		<key>Red Component</key>
+		<real>1</real>
+	</dict>
+	<key>Background Color</key>
+	<dict>
+		<key>Blue Component</key>
+		<real>0.2</real>
+		<key>Green Component</key>
+		<real>0.1</real>
+		<key>Red Component</key>
+		<real>0.1</real>
+	</dict>
+</dict>
+</plist>"#;
+
+        let (name, colors) = ThemeFormat::ITerm.import(contents).unwrap();
+
+        assert_eq!(name, None);
+        assert_eq!(colors.palette.red, Some(String::from("#ff8000")));
+        assert_eq!(colors.background, Some(String::from("#1a1a33")));
+        assert_eq!(colors.foreground, None);
+        assert!(ThemeFormat::ITerm.import("<plist></plist>").is_err());
+    }
+
+    #[test]
+    fn import_alacritty_reads_color_tables() {
+        let contents = "[colors.primary]\nbackground = '0x282a36'\nforeground = '#f8f8f2'\n\n[colors.cursor]\ncursor = 'CellForeground'\n\n[colors.normal]\nred = '#ff5555'\n\n[colors.bright]\nred = '#ff6e6e'\n";
+
+        let (_, colors) = ThemeFormat::Alacritty.import(contents).unwrap();
+
+        assert_eq!(colors.background, Some(String::from("#282a36")));
+        assert_eq!(colors.foreground, Some(String::from("#f8f8f2")));
+        assert_eq!(colors.cursor, None);
+        assert_eq!(colors.palette.red, Some(String::from("#ff5555")));
+        assert_eq!(colors.palette.bright_red, Some(String::from("#ff6e6e")));
+    }
+
+    #[test]
+    fn import_windows_terminal_reads_a_scheme() {
+        let contents = r##"{"name": "Campbell", "background": "#0C0C0C", "cursorColor": "#FFFFFF", "purple": "#881798", "brightPurple": "#B4009E", "selectionBackground": "#FFFFFF"}"##;
+
+        let (name, colors) = ThemeFormat::WindowsTerminal.import(contents).unwrap();
+
+        assert_eq!(name, Some(String::from("Campbell")));
+        assert_eq!(colors.background, Some(String::from("#0c0c0c")));
+        assert_eq!(colors.cursor, Some(String::from("#ffffff")));
+        assert_eq!(colors.palette.magenta, Some(String::from("#881798")));
+        assert_eq!(colors.palette.bright_magenta, Some(String::from("#b4009e")));
+        assert!(ThemeFormat::WindowsTerminal
+            .import(r#"{"schemes": []}"#)
+            .is_err());
+    }
+
+    #[test]
+    fn import_base16_maps_base_colors() {
+        let contents = "scheme: \"Ocean\"\nbase00: \"2b303b\"\nbase02: \"4f5b66\"\nbase05: \"c0c5ce\"\nbase08: \"bf616a\"\n";
+
+        let (name, colors) = ThemeFormat::Base16.import(contents).unwrap();
+
+        assert_eq!(name, Some(String::from("Ocean")));
+        assert_eq!(colors.background, Some(String::from("#2b303b")));
+        assert_eq!(colors.selection, Some(String::from("#4f5b66")));
+        assert_eq!(colors.palette.black, Some(String::from("#2b303b")));
+        assert_eq!(colors.palette.red, Some(String::from("#bf616a")));
+        assert_eq!(colors.palette.bright_red, Some(String::from("#bf616a")));
+        assert_eq!(colors.palette.white, Some(String::from("#c0c5ce")));
+
+        let newer = "system: \"base16\"\nname: \"Ocean\"\npalette:\n  base00: \"#2b303b\"\n";
+        let (name, colors) = ThemeFormat::Base16.import(newer).unwrap();
+        assert_eq!(name, Some(String::from("Ocean")));
+        assert_eq!(colors.background, Some(String::from("#2b303b")));
+    }
+
+    #[test]
+    fn import_theme_file_saves_to_themes_dir() {
+        let dir = TempDir::new("themes").unwrap();
+        let source = dir.path().join("downloaded.json");
+        fs::write(
+            &source,
+            r##"{"name": "Tokyo Night", "background": "#1a1b26"}"##,
+        )
+        .unwrap();
+        let themes_dir = dir.path().join("themes");
+
+        let name = import_theme_file(&source, &themes_dir).unwrap();
+
+        assert_eq!(name, "tokyo-night");
+        let theme = load_theme(&themes_dir, &name).unwrap();
+        assert_eq!(theme.background, Some(String::from("#1a1b26")));
+        assert!(import_theme_file(&dir.path().join("theme.txt"), &themes_dir).is_err());
+
+        let _ = dir.close();
+    }
+}
@@ -1,11 +1,59 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fmt, fs};
+use serde_json::Value;
+use std::{collections::HashMap, fmt, fs, io::Write, path::Path};
 
-#[derive(Debug, Serialize, Deserialize, Default, PartialEq)]
-#[serde(rename_all = "camelCase")]
+use crate::{
+    clipboard, command_registry,
+    config_diagnostics::{self, ConfigDiagnostic},
+    config_format::ConfigFormat,
+    config_migrations::{self, CURRENT_CONFIG_VERSION},
+    fonts::{Font, FontOverride},
+    hyperlinks,
+    keymap::{self, KeyBindings, KeySequence},
+    shell_integration,
+    themes::{self, Colors},
+};
+
+// written next to the config file so editors can offer completion and validation
+pub const SCHEMA_FILE_NAME: &str = ".alphacentauri.config.schema.json";
+
+// number of previous versions kept next to the config file as <file>.bak.1 (newest) to .bak.N
+const MAX_CONFIG_BACKUPS: usize = 5;
+
+// Every setting is optional, anything missing from the file is taken from
+// generate_default_user_config()
+#[derive(Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase", default)]
 pub struct UserConfigFS {
+    // lets editors find the schema, e.g. "./.alphacentauri.config.schema.json"
+    #[serde(rename = "$schema", skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    // the shape of the settings, older files are migrated on load (see config_migrations)
+    pub version: u64,
+    // other config files merged beneath this one, relative to this file
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
     pub shell: Shell,
-    pub keymaps: HashMap<String, String>,
+    // merged over the default keymaps, a combo or list of combos per command. An empty
+    // combo or list removes a default binding
+    pub keymaps: HashMap<String, KeyBindings>,
+    // a theme from the themes directory and colors that override it
+    pub colors: Colors,
+    pub font: Font,
+    pub window: Window,
+    pub notifications: Notifications,
+    pub clipboard: Clipboard,
+    pub hyperlinks: Hyperlinks,
+    pub profiles: HashMap<String, Profile>,
+    pub default_profile: Option<String>,
+    pub ssh: Ssh,
+}
+
+impl Default for UserConfigFS {
+    fn default() -> Self {
+        generate_default_user_config()
+    }
 }
 
 impl fmt::Display for UserConfigFS {
@@ -22,83 +70,431 @@ impl fmt::Display for UserConfigFS {
 pub struct UserConfigJS {
     pub shell: Shell,
     pub keymaps: Vec<KeyCommandMap>,
+    // the theme and overrides resolved, every color is set
+    pub colors: Colors,
+    pub font: Font,
+    pub window: Window,
+    pub notifications: Notifications,
+    pub clipboard: Clipboard,
+    pub hyperlinks: Hyperlinks,
+    pub profiles: Vec<NamedProfile>,
+    pub default_profile: Option<String>,
+    pub ssh: Ssh,
 }
 
-pub fn key_map_to_vector(h_map: HashMap<String, String>) -> Vec<KeyCommandMap> {
-    h_map
+pub fn user_config_to_js(config: &UserConfigFS, themes_dir: Option<&Path>) -> UserConfigJS {
+    UserConfigJS {
+        shell: config.shell.clone(),
+        keymaps: key_map_to_vector(config.keymaps.clone()),
+        colors: themes::resolve_colors(&config.colors, themes_dir).0,
+        font: config.font.clone(),
+        window: Window {
+            opacity: config.window.opacity.clamp(0.0, 1.0),
+            ..config.window.clone()
+        },
+        notifications: config.notifications.clone(),
+        clipboard: config.clipboard.clone(),
+        hyperlinks: config.hyperlinks.clone(),
+        profiles: profiles_to_vector(&config.profiles),
+        default_profile: config.default_profile.clone(),
+        ssh: config.ssh.clone(),
+    }
+}
+
+// Only commands in the registry can be bound, the user's bindings win over the defaults
+fn resolve_user_keymaps(keymaps: &HashMap<String, KeyBindings>) -> keymap::ResolvedKeymaps {
+    keymap::resolve_keymaps(
+        keymaps,
+        &command_registry::default_keymaps(),
+        command_registry::COMMANDS,
+    )
+}
+
+// Valid bindings with their combos normalized, e.g. "shift+Ctrl+C" becomes "ctrl+shift+c"
+pub fn key_map_to_vector(h_map: HashMap<String, KeyBindings>) -> Vec<KeyCommandMap> {
+    resolve_user_keymaps(&h_map)
+        .bindings
         .into_iter()
-        .map(|(key, value)| KeyCommandMap {
-            command_name: key,
-            key_combo: value,
+        .map(|(command_name, bindings)| KeyCommandMap {
+            command_name,
+            bindings: bindings
+                .into_iter()
+                .map(|binding| KeyBinding {
+                    key_combo: binding.sequence.to_string(),
+                    chords: binding.sequence,
+                    args: binding.args,
+                })
+                .collect(),
         })
         .collect()
 }
 
+// Why bindings were left out of key_map_to_vector()
+pub fn keymap_problems(keymaps: &HashMap<String, KeyBindings>) -> Vec<String> {
+    resolve_user_keymaps(keymaps).problems
+}
+
+// Why the theme or some colors were left out of the colors in user_config_to_js()
+pub fn color_problems(colors: &Colors, themes_dir: Option<&Path>) -> Vec<String> {
+    themes::resolve_colors(colors, themes_dir).1
+}
+
 #[derive(Debug, Serialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct KeyCommandMap {
     command_name: String,
-    key_combo: String,
+    bindings: Vec<KeyBinding>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq)]
+#[derive(Debug, Serialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
+pub struct KeyBinding {
+    // the normalized combo, chords separated by spaces
+    key_combo: String,
+    chords: KeySequence,
+    // e.g. the text for terminal:send_text, escapes already decoded
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    args: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase", default)]
 pub struct Shell {
+    // program, args, env values and envFile expand ~, $VAR, ${VAR} and ${VAR:-default}
     pub program: String,
     pub args: Vec<String>,
     pub env: HashMap<String, String>,
+    // a dotenv file loaded before env, relative paths are relative to the home directory
+    pub env_file: Option<String>,
     pub bell: bool,
-    pub fonts: String,
     pub change_directory_osc_code: i32,
     pub change_window_title_osc_code: i32,
 }
 
-// #[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq)]
-// #[serde(rename_all = "camelCase")]
-// pub struct Window {
+impl Default for Shell {
+    fn default() -> Self {
+        generate_default_user_config().shell
+    }
+}
+
+// OSC codes the app handles itself, a directory code equal to one of them would take it over
+const RESERVED_OSC_CODES: [&str; 4] = [
+    hyperlinks::HYPERLINK_OSC_CODE,
+    clipboard::CLIPBOARD_OSC_CODE,
+    shell_integration::SEMANTIC_PROMPT_CODE,
+    shell_integration::VSCODE_CODE,
+];
+
+impl Shell {
+    // None when the configured code is reserved, directory changes are then not tracked
+    pub fn change_directory_code(&self) -> Option<String> {
+        let code = self.change_directory_osc_code.to_string();
+        (!RESERVED_OSC_CODES.contains(&code.as_str())).then_some(code)
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.change_directory_code().is_none() {
+            problems.push(format!(
+                "shell.changeDirectoryOscCode: {} is already used by the terminal",
+                self.change_directory_osc_code
+            ));
+        }
+        problems
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Notifications {
+    // notify when a command in an unfocused pane runs at least this many seconds
+    pub long_running_command_seconds: u64,
+    pub long_running_command_enabled: bool,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Notifications {
+            long_running_command_seconds: 30,
+            long_running_command_enabled: true,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ClipboardPermission {
+    Allow,
+    Deny,
+    Prompt,
+}
+
+// Policy for programs accessing the system clipboard through OSC 52
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Clipboard {
+    pub write: ClipboardPermission,
+    pub read: ClipboardPermission,
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Clipboard {
+            write: ClipboardPermission::Allow,
+            read: ClipboardPermission::Prompt,
+        }
+    }
+}
+
+// OSC 8 links are only opened when their URL scheme is in this list. Schemes other than http,
+// https, mailto and file are refused by the app's open scope even when listed
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Hyperlinks {
+    pub allowed_schemes: Vec<String>,
+}
+
+impl Default for Hyperlinks {
+    fn default() -> Self {
+        Hyperlinks {
+            allowed_schemes: Vec::from([
+                String::from("http"),
+                String::from("https"),
+                String::from("file"),
+            ]),
+        }
+    }
+}
+
+// A named launch configuration, anything left unset falls back to the shell block
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Profile {
+    pub program: Option<String>,
+    pub args: Option<Vec<String>>,
+    pub env: HashMap<String, String>,
+    pub env_file: Option<String>,
+    pub cwd: Option<String>,
+    pub title: Option<String>,
+    // merged over the font section for sessions of this profile
+    pub font: Option<FontOverride>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NamedProfile {
+    pub name: String,
+    #[serde(flatten)]
+    pub profile: Profile,
+}
+
+pub fn profiles_to_vector(profiles: &HashMap<String, Profile>) -> Vec<NamedProfile> {
+    let mut named: Vec<NamedProfile> = profiles
+        .iter()
+        .map(|(name, profile)| NamedProfile {
+            name: name.clone(),
+            profile: profile.clone(),
+        })
+        .collect();
+    named.sort_by(|a, b| a.name.cmp(&b.name));
+    named
+}
+
+// The program, args, env and cwd a session should be launched with
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaunchSettings {
+    pub program: String,
+    pub args: Vec<String>,
+    pub env: HashMap<String, String>,
+    pub env_file: Option<String>,
+    pub cwd: Option<String>,
+    // names the session's tab until the shell sets a title
+    pub title: Option<String>,
+    // when the profile changes the font section's settings
+    pub font: Option<Font>,
+}
+
+// Returns None when a profile was requested by name but is not configured
+pub fn resolve_launch_settings(
+    config: &UserConfigFS,
+    profile_name: Option<&str>,
+) -> Option<LaunchSettings> {
+    let mut settings = LaunchSettings {
+        program: config.shell.program.clone(),
+        args: config.shell.args.clone(),
+        env: config.shell.env.clone(),
+        env_file: config.shell.env_file.clone(),
+        cwd: None,
+        title: None,
+        font: None,
+    };
 
-// }
+    let profile = match profile_name.or(config.default_profile.as_deref()) {
+        Some(name) => match config.profiles.get(name) {
+            Some(profile) => profile,
+            // a stale default profile should not stop shells from launching
+            None if profile_name.is_none() => return Some(settings),
+            None => return None,
+        },
+        None => return Some(settings),
+    };
+
+    if let Some(program) = &profile.program {
+        settings.program = program.clone();
+    }
+    if let Some(args) = &profile.args {
+        settings.args = args.clone();
+    }
+    settings.env.extend(profile.env.clone());
+    if let Some(env_file) = &profile.env_file {
+        settings.env_file = Some(env_file.clone());
+    }
+    settings.cwd = profile.cwd.clone();
+    settings.title = profile.title.clone();
+    settings.font = profile
+        .font
+        .as_ref()
+        .map(|font_override| font_override.apply(&config.font));
+    Some(settings)
+}
+
+// Display overrides for hosts found in ~/.ssh/config, keyed by host name
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Ssh {
+    pub hosts: HashMap<String, SshHostSettings>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SshHostSettings {
+    pub title: Option<String>,
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum StartupMode {
+    Normal,
+    Maximized,
+    Fullscreen,
+}
+
+// Space between the edge of a pane and the terminal, in pixels
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Padding {
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Default for Padding {
+    fn default() -> Self {
+        Padding { x: 12, y: 0 }
+    }
+}
+
+// The estimated size of a cell, relative to the font size, before the webview measures the font
+const CELL_WIDTH_EM: f64 = 0.6;
+const CELL_HEIGHT_EM: f64 = 1.2;
+const TAB_BAR_HEIGHT: f64 = 24.0;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Window {
+    // of the terminal background, from 0 (transparent) to 1 (opaque)
+    pub opacity: f64,
+    // the title bar and borders drawn by the system
+    pub decorations: bool,
+    // the size of a new window in cells, the previous size is restored when these aren't set
+    pub columns: Option<u32>,
+    pub rows: Option<u32>,
+    pub padding: Padding,
+    pub startup_mode: StartupMode,
+    pub always_on_top: bool,
+    // show the tab bar even when there is only one tab
+    pub force_tab_bar: bool,
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Window {
+            opacity: 1.0,
+            decorations: true,
+            columns: None,
+            rows: None,
+            padding: Padding::default(),
+            startup_mode: StartupMode::Normal,
+            always_on_top: false,
+            force_tab_bar: false,
+        }
+    }
+}
+
+impl Window {
+    // The logical size of a window that fits columns by rows cells of the font, when both
+    // are set
+    pub fn inner_size(&self, font: &Font) -> Option<(f64, f64)> {
+        let columns = self.columns.filter(|columns| *columns > 0)?;
+        let rows = self.rows.filter(|rows| *rows > 0)?;
+        let cell_width = font.size * CELL_WIDTH_EM;
+        let cell_height = (font.size * CELL_HEIGHT_EM * font.line_height).ceil();
+        Some((
+            (cell_width * columns as f64 + 2.0 * self.padding.x as f64).ceil(),
+            (cell_height * rows as f64 + 2.0 * self.padding.y as f64 + TAB_BAR_HEIGHT).ceil(),
+        ))
+    }
+
+    // The opacity is clamped and sizes of 0 are ignored when the window is set up
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !(0.0..=1.0).contains(&self.opacity) {
+            problems.push(format!(
+                "window.opacity: {} is not between 0 and 1",
+                self.opacity
+            ));
+        }
+        if self.columns == Some(0) || self.rows == Some(0) {
+            problems.push(String::from(
+                "window.columns and window.rows must be at least 1",
+            ));
+        }
+        if self.columns.is_some() != self.rows.is_some() {
+            problems.push(String::from(
+                "window.columns and window.rows are only used when both are set",
+            ));
+        }
+        problems
+    }
+}
 
 pub fn generate_default_user_config() -> UserConfigFS {
     UserConfigFS {
+        schema: Some(format!("./{}", SCHEMA_FILE_NAME)),
+        version: CURRENT_CONFIG_VERSION,
+        include: Vec::new(),
         shell: Shell {
             program: String::default(),
             args: Vec::default(),
             env: HashMap::default(),
+            env_file: None,
             bell: true,
-            fonts: String::from("Consolas, Monospace"),
             change_directory_osc_code: 7,
             #[cfg(target_os = "windows")]
             change_window_title_osc_code: 0,
             #[cfg(not(target_os = "windows"))]
             change_window_title_osc_code: 2,
         },
-        keymaps: HashMap::from([
-            (String::from("edit:copy"), String::from("ctrl+shift+c")),
-            (String::from("edit:paste"), String::from("ctrl+shift+v")),
-            (
-                String::from("edit:select_all"),
-                String::from("ctrl+shift+a"),
-            ),
-            (String::from("edit:interrupt"), String::from("ctrl+c")),
-            (String::from("window:new_tab"), String::from("ctrl+shift+t")),
-            (
-                String::from("window:next_tab"),
-                String::from("ctrl+shift+ArrowRight"),
-            ),
-            (
-                String::from("window:prev_tab"),
-                String::from("ctrl+shift+ArrowLeft"),
-            ),
-            (
-                String::from("window:split_right"),
-                String::from("ctrl+shift+d"),
-            ),
-            (
-                String::from("window:split_down"),
-                String::from("ctrl+shift+e"),
-            ),
-        ]),
+        keymaps: command_registry::default_keymaps(),
+        colors: Colors::default(),
+        font: Font::default(),
+        window: Window::default(),
+        notifications: Notifications::default(),
+        clipboard: Clipboard::default(),
+        hyperlinks: Hyperlinks::default(),
+        profiles: HashMap::default(),
+        default_profile: None,
+        ssh: Ssh::default(),
     }
 }
 
@@ -107,6 +503,7 @@ pub enum UserConfigError {
     Read(String),
     Write(String),
     Parse(String),
+    Invalid(Vec<ConfigDiagnostic>),
 }
 
 impl fmt::Display for UserConfigError {
@@ -115,27 +512,274 @@ impl fmt::Display for UserConfigError {
               UserConfigError::Read(e) => write!(f, "<div class='friendly-error-message'>Unable to load User Configuration file.</div><div class='raw-error ml-4 mt-2'>{}</div>", e),
               UserConfigError::Write(e) => write!(f, "<div class='friendly-error-message'>Unable to save User Configuration file.</div><div class='raw-error ml-4 mt-2'>{}</div>", e),
               UserConfigError::Parse(e) => write!(f, "<div class='friendly-error-message'>User Configuration file found, but could not be parsed.</div><div class='raw-error ml-4 mt-2'>{}</div>", e),
+              UserConfigError::Invalid(diagnostics) => write!(f, "<div class='friendly-error-message'>User Configuration file found, but some settings are invalid.</div>{}", format_diagnostics(diagnostics)),
           }
     }
 }
 
 pub type Result<T> = std::result::Result<T, UserConfigError>;
 
-pub fn save_user_configuration(file_loc: &str, config: &UserConfigFS) -> Result<()> {
-    let json = serde_json::to_string_pretty(&config)
-        .map_err(|e| UserConfigError::Write(format!("{:?}", e)))?;
-    fs::write(file_loc, json).map_err(|e| UserConfigError::Write(format!("{:?}", e)))
+pub fn format_diagnostics(diagnostics: &[ConfigDiagnostic]) -> String {
+    diagnostics
+        .iter()
+        .map(|d| format!("<div class='raw-error ml-4 mt-2'>{}</div>", d))
+        .collect()
+}
+
+pub fn generate_user_config_schema() -> String {
+    let schema = schemars::schema_for!(UserConfigFS);
+    serde_json::to_string_pretty(&schema).unwrap_or_default()
+}
+
+// Writes the schema next to the config file, skipping the write when it is already current.
+// Returns the location of the schema file
+pub fn publish_user_config_schema(file_loc: &str) -> Result<String> {
+    let schema_loc = Path::new(file_loc)
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(SCHEMA_FILE_NAME)
+        .to_string_lossy()
+        .to_string();
+    let schema = generate_user_config_schema();
+
+    if fs::read_to_string(&schema_loc).is_ok_and(|current| current == schema) {
+        return Ok(schema_loc);
+    }
+    fs::write(&schema_loc, schema).map_err(|e| write_error(&schema_loc, e))?;
+    Ok(schema_loc)
+}
+
+fn write_error(file_loc: &str, e: impl fmt::Debug) -> UserConfigError {
+    UserConfigError::Write(format!("{}: {:?}", file_loc, e))
+}
+
+fn backup_path(file_loc: &str, index: usize) -> String {
+    format!("{}.bak.{}", file_loc, index)
+}
+
+fn backup_user_configuration(file_loc: &str) -> Result<()> {
+    if fs::metadata(file_loc).is_err() {
+        return Ok(());
+    }
+    for index in (1..MAX_CONFIG_BACKUPS).rev() {
+        let from = backup_path(file_loc, index);
+        if fs::metadata(&from).is_ok() {
+            let to = backup_path(file_loc, index + 1);
+            fs::rename(&from, &to).map_err(|e| write_error(&to, e))?;
+        }
+    }
+    let newest = backup_path(file_loc, 1);
+    fs::copy(file_loc, &newest).map_err(|e| write_error(&newest, e))?;
+    Ok(())
+}
+
+// Writes to a temp file next to the config and renames it into place, so a crash or a full
+// disk can never leave a half written config behind
+fn write_user_configuration(file_loc: &str, value: &Value) -> Result<()> {
+    let contents = ConfigFormat::from_path(file_loc)
+        .serialize(value)
+        .map_err(|e| write_error(file_loc, e))?;
+
+    backup_user_configuration(file_loc)?;
+
+    let temp_loc = format!("{}.tmp", file_loc);
+    let mut temp_file = fs::File::create(&temp_loc).map_err(|e| write_error(&temp_loc, e))?;
+    temp_file
+        .write_all(contents.as_bytes())
+        .and_then(|_| temp_file.sync_all())
+        .map_err(|e| write_error(&temp_loc, e))?;
+    drop(temp_file);
+
+    fs::rename(&temp_loc, file_loc).map_err(|e| {
+        let _ = fs::remove_file(&temp_loc);
+        write_error(file_loc, e)
+    })
+}
+
+// Saves a full config, or just some settings, as JSON, TOML or YAML depending on the file
+// extension
+pub fn save_user_configuration<T: Serialize>(file_loc: &str, settings: &T) -> Result<()> {
+    let value = serde_json::to_value(settings).map_err(|e| write_error(file_loc, e))?;
+    write_user_configuration(file_loc, &value)
+}
+
+// Objects are merged key by key so a file only needs the settings it changes, anything else
+// (arrays, strings, numbers) replaces the default value
+pub fn merge_json(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn merge_over_defaults(value: Value) -> Value {
+    let mut merged = serde_json::to_value(generate_default_user_config()).unwrap_or_default();
+    merge_json(&mut merged, value);
+    merged
+}
+
+pub fn parse_user_configuration(value: Value) -> Result<UserConfigFS> {
+    serde_path_to_error::deserialize(merge_over_defaults(value))
+        .map_err(|e| UserConfigError::Parse(format!("{}: {}", e.path(), e.inner())))
+}
+
+// Sets the value at a JSON pointer (RFC 6901) such as /shell/bell, creating the last
+// key when its parent object exists, and validates the result
+pub fn update_user_configuration(
+    config: &UserConfigFS,
+    pointer: &str,
+    value: Value,
+) -> Result<UserConfigFS> {
+    let mut json =
+        serde_json::to_value(config).map_err(|e| UserConfigError::Parse(format!("{:?}", e)))?;
+
+    if pointer.is_empty() {
+        return parse_user_configuration(value);
+    }
+    let (parent_pointer, last) = pointer
+        .rsplit_once('/')
+        .ok_or_else(|| UserConfigError::Parse(format!("Invalid JSON pointer: {}", pointer)))?;
+    let key = last.replace("~1", "/").replace("~0", "~");
+
+    match json.pointer_mut(parent_pointer) {
+        Some(Value::Object(map)) => {
+            map.insert(key, value);
+        }
+        Some(Value::Array(array)) => {
+            if key == "-" {
+                array.push(value);
+            } else {
+                let index: usize = key.parse().map_err(|_| {
+                    UserConfigError::Parse(format!("Invalid array index in {}", pointer))
+                })?;
+                match array.get_mut(index) {
+                    Some(item) => *item = value,
+                    None => {
+                        return Err(UserConfigError::Parse(format!(
+                            "Array index out of bounds in {}",
+                            pointer
+                        )))
+                    }
+                }
+            }
+        }
+        _ => {
+            return Err(UserConfigError::Parse(format!(
+                "No configuration setting found at {}",
+                parent_pointer
+            )))
+        }
+    }
+
+    parse_user_configuration(json)
+}
+
+// Returns the settings as written in the file along with the resolved config and warnings
+pub fn read_user_configuration(
+    file_loc: &str,
+) -> Result<(Value, UserConfigFS, Vec<ConfigDiagnostic>)> {
+    let source =
+        fs::read_to_string(file_loc).map_err(|e| UserConfigError::Read(format!("{:?}", e)))?;
+    let format = ConfigFormat::from_path(file_loc);
+    let mut value = format.parse(&source).map_err(UserConfigError::Invalid)?;
+    // files are migrated in memory, the user's file is only rewritten when they ask
+    let version_warning = config_migrations::migrate(&mut value).err().map(|message| {
+        let (line, column) = format.locate(
+            &source,
+            &[config_diagnostics::PathSegment::Key(String::from(
+                "version",
+            ))],
+        );
+        ConfigDiagnostic {
+            file: None,
+            path: String::from("version"),
+            line,
+            column,
+            message,
+        }
+    });
+    let (config, mut warnings) =
+        config_diagnostics::deserialize_with_diagnostics(merge_over_defaults(value.clone()), |s| {
+            format.locate(&source, s)
+        })
+        .map_err(UserConfigError::Invalid)?;
+    warnings.extend(version_warning);
+    Ok((value, config, warnings))
+}
+
+// The file migrated in memory and what the migrations changed, nothing when the file is
+// current or can't be parsed (reading it reports the error)
+fn migrated_file(file_loc: &str) -> Option<(Value, Vec<String>)> {
+    let source = fs::read_to_string(file_loc).ok()?;
+    let mut value = ConfigFormat::from_path(file_loc).parse(&source).ok()?;
+    let changes = config_migrations::migrate(&mut value).unwrap_or_default();
+    Some((value, changes))
+}
+
+// What upgrading a config file written for an older version would change, the file is left
+// as it is since rewriting it drops its comments and formatting
+pub fn pending_migrations(file_loc: &str) -> Vec<String> {
+    migrated_file(file_loc)
+        .map(|(_, changes)| changes)
+        .unwrap_or_default()
+}
+
+// Upgrades a config file written for an older version in place once the user agreed, the
+// previous file is kept as <file>.bak.1. Returns what changed
+pub fn migrate_user_configuration(file_loc: &str) -> Result<Vec<String>> {
+    let Some((value, changes)) = migrated_file(file_loc) else {
+        return Ok(Vec::new());
+    };
+    if changes.is_empty() {
+        return Ok(changes);
+    }
+
+    #[cfg(debug_assertions)]
+    println!(
+        "Migrating configuration file at {} to version {}.",
+        file_loc, CURRENT_CONFIG_VERSION
+    );
+
+    write_user_configuration(file_loc, &value)?;
+    Ok(changes)
+}
+
+// where migrate_user_configuration() keeps the file as it was before the upgrade
+pub fn migration_backup_path(file_loc: &str) -> String {
+    backup_path(file_loc, 1)
 }
 
-pub fn get_user_configuration(file_loc: &str, save_default_config: bool) -> Result<UserConfigFS> {
+// Rewrites a config file in the format of the output file's extension. Only the settings
+// written in the input are carried over, defaults are not filled in
+pub fn convert_user_configuration(
+    input_loc: &str,
+    output_loc: &str,
+) -> Result<Vec<ConfigDiagnostic>> {
+    let (value, _, warnings) = read_user_configuration(input_loc)?;
+    write_user_configuration(output_loc, &value)?;
+    Ok(warnings)
+}
+
+// Loads the config along with warnings about settings that were ignored, such as unknown keys
+pub fn get_user_configuration(
+    file_loc: &str,
+    save_default_config: bool,
+) -> Result<(UserConfigFS, Vec<ConfigDiagnostic>)> {
     match fs::metadata(file_loc) {
         Ok(_) => {
             #[cfg(debug_assertions)]
             println!("Configuration file at {} found.", file_loc);
 
-            let json = fs::read_to_string(file_loc)
-                .map_err(|e| UserConfigError::Read(format!("{:?}", e)))?;
-            serde_json::from_str(&json).map_err(|e| UserConfigError::Parse(format!("{:?}", e)))
+            read_user_configuration(file_loc).map(|(_, config, warnings)| (config, warnings))
         }
         Err(_) => {
             #[cfg(debug_assertions)]
@@ -144,12 +788,18 @@ pub fn get_user_configuration(file_loc: &str, save_default_config: bool) -> Resu
                 file_loc
             );
 
-            let default_config = generate_default_user_config();
+            // only the schema reference is written, so defaults from a system wide config file
+            // are not shadowed by a copy of the built-in ones
             if save_default_config {
-                save_user_configuration(file_loc, &default_config).map(|_| default_config)
-            } else {
-                Ok(default_config)
+                let mut minimal = serde_json::Map::new();
+                minimal.insert(
+                    String::from("$schema"),
+                    Value::String(format!("./{}", SCHEMA_FILE_NAME)),
+                );
+                minimal.insert(String::from("version"), Value::from(CURRENT_CONFIG_VERSION));
+                save_user_configuration(file_loc, &Value::Object(minimal))?;
             }
+            Ok((generate_default_user_config(), Vec::new()))
         }
     }
 }
@@ -159,33 +809,83 @@ mod tests {
     use std::{any::Any, io::Write};
 
     use super::*;
+    use crate::keymap::KeyBindingConfig;
     use tempdir::TempDir;
 
     #[test]
     fn key_map_to_vector_returns_vector() {
-        let map: HashMap<String, String> = HashMap::from([
-            ("edit:interrupt".to_string(), "ctrl+c".to_string()),
-            ("window:new_tab".to_string(), "ctrl+shift+t".to_string()),
+        let map: HashMap<String, KeyBindings> = HashMap::from([
+            ("edit:interrupt".to_string(), KeyBindings::from("ctrl+c")),
+            (
+                "window:new_tab".to_string(),
+                KeyBindings::Many(Vec::from([
+                    KeyBindingConfig::from("ctrl+shift+t"),
+                    KeyBindingConfig::from("ctrl+a C"),
+                ])),
+            ),
         ]);
+        let binding = |key_combo: &str| KeyBinding {
+            key_combo: key_combo.to_string(),
+            chords: KeySequence::parse(key_combo).unwrap(),
+            args: HashMap::new(),
+        };
 
-        let mut expected_vector: Vec<KeyCommandMap> = Vec::from([
+        let expected_vector: Vec<KeyCommandMap> = Vec::from([
             KeyCommandMap {
                 command_name: "edit:interrupt".to_string(),
-                key_combo: "ctrl+c".to_string(),
+                bindings: Vec::from([binding("ctrl+c")]),
             },
             KeyCommandMap {
                 command_name: "window:new_tab".to_string(),
-                key_combo: "ctrl+shift+t".to_string(),
+                bindings: Vec::from([binding("ctrl+shift+t"), binding("ctrl+a c")]),
             },
         ]);
-        expected_vector.sort_by(|a, b| a.command_name.cmp(&b.command_name));
 
-        let mut actual_vector = key_map_to_vector(map);
-        actual_vector.sort_by(|a, b| a.command_name.cmp(&b.command_name));
+        let actual_vector = key_map_to_vector(map);
 
         assert_eq!(actual_vector, expected_vector);
     }
 
+    #[test]
+    fn resolve_launch_settings_applies_profile_overrides() {
+        let mut config = generate_default_user_config();
+        config.shell.program = String::from("bash");
+        config.shell.env = HashMap::from([
+            (String::from("TERM"), String::from("xterm-256color")),
+            (String::from("EDITOR"), String::from("vi")),
+        ]);
+        config.profiles = HashMap::from([(
+            String::from("python"),
+            Profile {
+                program: Some(String::from("python3")),
+                env: HashMap::from([(String::from("EDITOR"), String::from("nvim"))]),
+                cwd: Some(String::from("/srv")),
+                title: Some(String::from("Python")),
+                font: Some(FontOverride {
+                    size: Some(20.0),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        )]);
+        config.default_profile = Some(String::from("python"));
+
+        let actual = resolve_launch_settings(&config, None).unwrap();
+
+        assert_eq!(actual.program, "python3");
+        assert_eq!(actual.args, Vec::<String>::new());
+        assert_eq!(actual.env.get("TERM").unwrap(), "xterm-256color");
+        assert_eq!(actual.env.get("EDITOR").unwrap(), "nvim");
+        assert_eq!(actual.cwd, Some(String::from("/srv")));
+        assert_eq!(actual.title, Some(String::from("Python")));
+        assert_eq!(actual.font.as_ref().map(|font| font.size), Some(20.0));
+        assert_eq!(
+            actual.font.map(|font| font.family),
+            Some(config.font.family.clone())
+        );
+        assert_eq!(resolve_launch_settings(&config, Some("missing")), None);
+    }
+
     #[test]
     fn save_user_configuration_saves_to_disk() {
         let dir = TempDir::new("usr_home").unwrap();
@@ -203,6 +903,77 @@ mod tests {
         let _ = dir.close();
     }
 
+    #[test]
+    fn save_user_configuration_keeps_backups() {
+        let dir = TempDir::new("usr_home").unwrap();
+        let file_path = dir.path().join("userConfig.json");
+        let file_path_str = file_path.to_str().unwrap();
+
+        let mut config = generate_default_user_config();
+        for program in ["bash", "zsh", "fish"] {
+            config.shell.program = String::from(program);
+            save_user_configuration(file_path_str, &config).unwrap();
+        }
+
+        let newest_backup = get_user_configuration(&backup_path(file_path_str, 1), false)
+            .unwrap()
+            .0;
+        let oldest_backup = get_user_configuration(&backup_path(file_path_str, 2), false)
+            .unwrap()
+            .0;
+        assert_eq!(newest_backup.shell.program, "zsh");
+        assert_eq!(oldest_backup.shell.program, "bash");
+        assert!(fs::metadata(backup_path(file_path_str, 3)).is_err());
+        assert!(fs::metadata(format!("{}.tmp", file_path_str)).is_err());
+
+        let _ = dir.close();
+    }
+
+    #[test]
+    fn update_user_configuration_sets_value_at_pointer() {
+        let config = generate_default_user_config();
+
+        let actual = update_user_configuration(
+            &config,
+            "/keymaps/edit:copy",
+            Value::String(String::from("ctrl+c")),
+        )
+        .unwrap();
+        assert_eq!(
+            actual.keymaps.get("edit:copy").unwrap(),
+            &KeyBindings::from("ctrl+c")
+        );
+
+        let actual = update_user_configuration(&config, "/shell/bell", Value::Bool(false)).unwrap();
+        assert!(!actual.shell.bell);
+
+        let invalid =
+            update_user_configuration(&config, "/shell/bell", Value::String(String::from("loud")));
+        assert!(matches!(invalid, Err(UserConfigError::Parse(_))));
+        assert!(update_user_configuration(&config, "/missing/key", Value::Null).is_err());
+    }
+
+    #[test]
+    fn publish_user_config_schema_writes_schema_next_to_config() {
+        let dir = TempDir::new("usr_home").unwrap();
+        let file_path = dir.path().join("userConfig.json");
+
+        let schema_loc = publish_user_config_schema(file_path.to_str().unwrap()).unwrap();
+
+        assert_eq!(
+            std::path::PathBuf::from(&schema_loc),
+            dir.path().join(SCHEMA_FILE_NAME)
+        );
+        let schema: Value = serde_json::from_str(&fs::read_to_string(schema_loc).unwrap()).unwrap();
+        assert!(schema.pointer("/properties/shell").is_some());
+        assert!(schema.pointer("/properties/$schema").is_some());
+        assert!(schema
+            .pointer("/definitions/Shell/properties/changeDirectoryOscCode")
+            .is_some());
+
+        let _ = dir.close();
+    }
+
     #[test]
     fn get_user_configuration_loads_config_from_file() {
         let dir = TempDir::new("usr_home").unwrap();
@@ -212,8 +983,9 @@ mod tests {
         let default_config = generate_default_user_config();
         let _ = save_user_configuration(file_path_str, &default_config);
 
-        let actual = get_user_configuration(file_path_str, false).unwrap();
+        let (actual, warnings) = get_user_configuration(file_path_str, false).unwrap();
         assert_eq!(actual, default_config);
+        assert!(warnings.is_empty());
 
         let _ = dir.close();
     }
@@ -241,6 +1013,143 @@ mod tests {
         let _ = dir.close();
     }
 
+    #[test]
+    fn get_user_configuration_reports_invalid_settings() {
+        let dir = TempDir::new("usr_home").unwrap();
+        let file_path = dir.path().join("userConfig.json");
+        let file_path_str = file_path.to_str().unwrap();
+
+        let mut json = serde_json::to_value(generate_default_user_config()).unwrap();
+        json["clipboard"]["read"] = Value::String(String::from("sometimes"));
+        json["shell"]["colour"] = Value::String(String::from("red"));
+        fs::write(file_path_str, serde_json::to_string_pretty(&json).unwrap()).unwrap();
+
+        match get_user_configuration(file_path_str, false) {
+            Err(UserConfigError::Invalid(diagnostics)) => {
+                assert_eq!(diagnostics.len(), 1);
+                assert_eq!(diagnostics[0].path, "clipboard.read");
+                assert!(diagnostics[0].line > 1);
+            }
+            other => panic!("expected invalid settings, got {:?}", other),
+        }
+
+        json["clipboard"]["read"] = Value::String(String::from("deny"));
+        fs::write(file_path_str, serde_json::to_string_pretty(&json).unwrap()).unwrap();
+
+        let (actual, warnings) = get_user_configuration(file_path_str, false).unwrap();
+        assert_eq!(actual.clipboard.read, ClipboardPermission::Deny);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].path, "shell.colour");
+
+        let _ = dir.close();
+    }
+
+    #[test]
+    fn get_user_configuration_merges_partial_config_over_defaults() {
+        let dir = TempDir::new("usr_home").unwrap();
+        let file_path = dir.path().join("userConfig.json");
+        let file_path_str = file_path.to_str().unwrap();
+        fs::write(
+            file_path_str,
+            "{\"shell\":{\"program\":\"fish\"},\"keymaps\":{\"edit:copy\":\"ctrl+c\",\"edit:paste\":\"\",\"window:close_tab\":\"ctrl+w\"}}",
+        )
+        .unwrap();
+        let default_config = generate_default_user_config();
+
+        let (actual, warnings) = get_user_configuration(file_path_str, false).unwrap();
+
+        assert!(warnings.is_empty());
+        assert_eq!(actual.shell.program, "fish");
+        assert_eq!(actual.font, default_config.font);
+        assert_eq!(actual.shell.bell, default_config.shell.bell);
+        assert_eq!(actual.notifications, default_config.notifications);
+        assert_eq!(
+            actual.keymaps.get("edit:copy").unwrap(),
+            &KeyBindings::from("ctrl+c")
+        );
+        assert_eq!(
+            actual.keymaps.get("window:close_tab").unwrap(),
+            &KeyBindings::from("ctrl+w")
+        );
+        assert_eq!(
+            actual.keymaps.get("window:new_tab"),
+            default_config.keymaps.get("window:new_tab")
+        );
+        assert!(!key_map_to_vector(actual.keymaps)
+            .iter()
+            .any(|map| map.command_name == "edit:paste"));
+
+        let _ = dir.close();
+    }
+
+    #[test]
+    fn migrate_user_configuration_upgrades_file_and_keeps_backup() {
+        let dir = TempDir::new("usr_home").unwrap();
+        let file_path = dir.path().join("userConfig.json");
+        let file_path_str = file_path.to_str().unwrap();
+        let old_config = format!(
+            "{{\"shell\":{{\"program\":\"fish\",\"args\":[],\"env\":{{}},\"bell\":true,\"fonts\":\"Hack\",\"changeDirectoryOscCode\":7,\"changeWindowTitleOscCode\":{}}},\"keymaps\":{{\"edit:copy\":\"ctrl+shift+c\"}}}}",
+            if cfg!(target_os = "windows") { 0 } else { 2 }
+        );
+        fs::write(file_path_str, &old_config).unwrap();
+
+        assert_eq!(pending_migrations(file_path_str).len(), 2);
+        assert_eq!(fs::read_to_string(file_path_str).unwrap(), old_config);
+        let changes = migrate_user_configuration(file_path_str).unwrap();
+
+        assert_eq!(changes.len(), 2);
+        let (actual, _, _) = read_user_configuration(file_path_str).unwrap();
+        assert_eq!(
+            actual,
+            serde_json::json!({
+                "shell": {"program": "fish"},
+                "font": {"family": ["Hack"]},
+                "version": CURRENT_CONFIG_VERSION
+            })
+        );
+        assert_eq!(
+            fs::read_to_string(migration_backup_path(file_path_str)).unwrap(),
+            old_config
+        );
+        assert!(migrate_user_configuration(file_path_str)
+            .unwrap()
+            .is_empty());
+
+        let _ = dir.close();
+    }
+
+    #[test]
+    fn convert_user_configuration_keeps_settings_across_formats() {
+        let dir = TempDir::new("usr_home").unwrap();
+        let json_path = dir.path().join("userConfig.json");
+        let toml_path = dir.path().join("userConfig.toml");
+        let yaml_path = dir.path().join("userConfig.yaml");
+        let json_path_str = json_path.to_str().unwrap();
+        let toml_path_str = toml_path.to_str().unwrap();
+        let yaml_path_str = yaml_path.to_str().unwrap();
+
+        let mut config = generate_default_user_config();
+        config.shell.program = String::from("fish");
+        config.default_profile = Some(String::from("work"));
+        save_user_configuration(json_path_str, &config).unwrap();
+
+        convert_user_configuration(json_path_str, toml_path_str).unwrap();
+        convert_user_configuration(toml_path_str, yaml_path_str).unwrap();
+
+        assert_eq!(
+            get_user_configuration(toml_path_str, false).unwrap().0,
+            config
+        );
+        assert_eq!(
+            get_user_configuration(yaml_path_str, false).unwrap().0,
+            config
+        );
+        let toml = fs::read_to_string(toml_path_str).unwrap();
+        assert!(toml.contains("program = \"fish\""));
+
+        let _ = dir.close();
+    }
+
     #[test]
     fn get_user_configuration_returns_parse_error() {
         let dir = TempDir::new("usr_home").unwrap();
@@ -253,7 +1162,7 @@ mod tests {
         f.sync_all().unwrap();
 
         let actual = match get_user_configuration(file_path_str, false) {
-            Ok(json) => json.type_id(),
+            Ok((json, _)) => json.type_id(),
             Err(e) => e.type_id(),
         };
 
@@ -261,4 +1170,65 @@ mod tests {
 
         let _ = dir.close();
     }
+
+    #[test]
+    fn window_inner_size_fits_cells_of_font() {
+        let font = Font {
+            size: 10.0,
+            line_height: 1.5,
+            ..Font::default()
+        };
+        let window = Window {
+            columns: Some(80),
+            rows: Some(24),
+            padding: Padding { x: 5, y: 2 },
+            ..Window::default()
+        };
+
+        assert_eq!(window.inner_size(&font), Some((490.0, 460.0)));
+        assert!(window.problems().is_empty());
+        assert_eq!(Window::default().inner_size(&font), None);
+
+        let invalid = Window {
+            opacity: 1.5,
+            rows: Some(0),
+            ..Window::default()
+        };
+        assert_eq!(invalid.problems().len(), 3);
+    }
+
+    #[test]
+    fn window_inner_size_ignores_zero_cells() {
+        let font = Font::default();
+        let no_columns = Window {
+            columns: Some(0),
+            rows: Some(24),
+            ..Window::default()
+        };
+        let no_rows = Window {
+            columns: Some(80),
+            rows: Some(0),
+            ..Window::default()
+        };
+
+        assert_eq!(no_columns.inner_size(&font), None);
+        assert_eq!(no_rows.inner_size(&font), None);
+        assert_eq!(no_rows.problems().len(), 1);
+    }
+
+    #[test]
+    fn reserved_change_directory_codes_are_ignored() {
+        let shell = Shell::default();
+        assert_eq!(shell.change_directory_code(), Some(String::from("7")));
+        assert!(shell.problems().is_empty());
+
+        for code in [8, 52, 133, 633] {
+            let reserved = Shell {
+                change_directory_osc_code: code,
+                ..Shell::default()
+            };
+            assert_eq!(reserved.change_directory_code(), None);
+            assert_eq!(reserved.problems().len(), 1);
+        }
+    }
 }
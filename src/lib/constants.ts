@@ -3,6 +3,8 @@ export const TAURI_COMMAND_GET_STARTUP_NOTIFICATIONS = 'get_startup_notification
 export const TAURI_COMMAND_GET_SYSTEM_INFO = 'get_system_info';
 
 export const TAURI_COMMAND_GET_USER_CONFIG = 'get_user_config';
+export const TAURI_COMMAND_TRUST_PROJECT_CONFIG = 'trust_project_config';
+export const TAURI_COMMAND_UPGRADE_USER_CONFIG = 'upgrade_user_config';
 
 export const TAURI_COMMAND_CREATE_SESSION = 'create_session';
 export const TAURI_COMMAND_READ_FROM_SESSION = 'read_from_session';
@@ -11,6 +13,8 @@ export const TAURI_COMMAND_CHECK_EXIT_STATUS = 'check_exit_status';
 export const TAURI_COMMAND_RESIZE = 'resize';
 export const TAURI_COMMAND_WRITE_TO_SESSION = 'write_to_session';
 export const TAURI_COMMAND_END_SESSION = 'end_session';
+export const TAURI_COMMAND_SET_FOCUSED_SESSION = 'set_focused_session';
+export const TAURI_COMMAND_RESPOND_TO_CLIPBOARD_REQUEST = 'respond_to_clipboard_request';
 
 export const WINDOW_COMMAND_NEW_TAB = 'window:new_tab';
 export const WINDOW_COMMAND_SPLIT_RIGHT = 'window:split_right';
@@ -1,5 +1,10 @@
 import { invoke } from '@tauri-apps/api/core';
-import type { CreateSessionInputs, SessionExitStatus, ShellSession } from '$lib/types';
+import type {
+  CreateSessionInputs,
+  CreatedSession,
+  SessionExitStatus,
+  ShellSession
+} from '$lib/types';
 import {
   TAURI_COMMAND_CREATE_SESSION,
   TAURI_COMMAND_RESIZE,
@@ -25,12 +30,12 @@ const createSession = async ({
   rows,
   currentWorkingDirectory,
   env,
-  referringSessionId
+  referringSessionId,
+  profile
 }: CreateSessionInputs) => {
   let pid: number | null = null;
   // eslint-disable-next-line prefer-const
   let rawCwd = '';
-  // eslint-disable-next-line prefer-const
   let title = '';
   let scrollbackBuffer = '';
   let shellOutputObservers: ((data: string) => void)[] = [];
@@ -38,14 +43,17 @@ const createSession = async ({
   let shellExited = false;
   let killCommandSent = false;
 
-  pid = await invoke<number>(TAURI_COMMAND_CREATE_SESSION, {
+  const created = await invoke<CreatedSession>(TAURI_COMMAND_CREATE_SESSION, {
     args,
     cols,
     rows,
     currentWorkingDirectory,
     env,
-    referringSessionId
+    referringSessionId,
+    profile
   });
+  pid = created.pid;
+  title = created.title ?? '';
   let sessionActive = true;
 
   const resize = (cols: number, rows: number) => {
@@ -150,6 +158,7 @@ const createSession = async ({
     pid,
     rawCwd,
     title,
+    font: created.font,
     resize,
     write,
     kill,
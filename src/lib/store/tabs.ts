@@ -1,7 +1,6 @@
 import type { Direction, PaneData, TabInfo, TreeNode } from '$lib/types';
 import { get, writable } from 'svelte/store';
 import { sessions } from './sessions';
-import { userConfiguration } from '.';
 import { createTooltip } from '@melt-ui/svelte';
 
 const lastNodeId = writable(0);
@@ -57,13 +56,11 @@ const createSingleNode = async ({
   };
 
   if (sessionId === undefined && createNewSession) {
-    const config = get(userConfiguration);
     let currentWorkingDirectory = undefined;
     if (referringSessionId !== undefined) {
       currentWorkingDirectory = sessions.get(referringSessionId)?.rawCwd;
     }
     const session = await sessions.createSession({
-      env: config.shell.env,
       currentWorkingDirectory,
       referringSessionId
     });
@@ -96,6 +93,9 @@ interface CreateTabArgs {
 const createTab = async ({ tabName, referringSessionId }: CreateTabArgs) => {
   const newTabId = '' + new Date().getTime();
   const newTree = await createSingleNode({ referringSessionId });
+  // a profile's title names the tab until the shell sets one
+  const sessionTitle =
+    newTree.data.sessionId !== undefined ? sessions.get(newTree.data.sessionId)?.title : undefined;
   const toolTip = createTooltip({
     positioning: {
       placement: 'bottom'
@@ -108,7 +108,7 @@ const createTab = async ({ tabName, referringSessionId }: CreateTabArgs) => {
   update(($tabs) => {
     $tabs.push({
       id: newTabId,
-      name: tabName !== undefined ? tabName : 'New Tab',
+      name: tabName !== undefined ? tabName : sessionTitle || 'New Tab',
       sessionTree: newTree,
       toolTip
     });
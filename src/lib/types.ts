@@ -1,9 +1,96 @@
+export interface Chord {
+  ctrl: boolean;
+  alt: boolean;
+  shift: boolean;
+  meta: boolean;
+  key: string;
+}
+
+export interface KeyBinding {
+  // normalized, chords separated by spaces e.g. 'ctrl+a c'
+  keyCombo: string;
+  chords: Chord[];
+  // e.g. the text for terminal:send_text, escapes are decoded by the backend
+  args?: { [name: string]: string };
+}
+
 export interface CommandKeyMap {
   commandName: string;
-  keyCombo: string;
+  bindings: KeyBinding[];
+}
+
+export interface CommandArg {
+  name: string;
+  description: string;
+  required: boolean;
+  escapes: boolean;
+}
+
+// returned by the get_commands backend command
+export interface CommandInfo {
+  id: string;
+  description: string;
+  category: 'edit' | 'terminal' | 'window';
+  defaultBindings: string[];
+  args: CommandArg[];
+}
+
+export interface Palette {
+  black: string;
+  red: string;
+  green: string;
+  yellow: string;
+  blue: string;
+  magenta: string;
+  cyan: string;
+  white: string;
+  brightBlack: string;
+  brightRed: string;
+  brightGreen: string;
+  brightYellow: string;
+  brightBlue: string;
+  brightMagenta: string;
+  brightCyan: string;
+  brightWhite: string;
+}
+
+// resolved by the backend from the theme and the colors section, every color is set
+export interface Colors {
+  theme?: string;
+  foreground: string;
+  background: string;
+  cursor: string;
+  selection: string;
+  palette: Palette;
+}
+
+export interface Font {
+  family: string[];
+  size: number;
+  lineHeight: number;
+  weight: string;
+  boldWeight: string;
+  ligatures: boolean;
+  // face names, empty uses the matching face of the regular family
+  boldFamily: string[];
+  italicFamily: string[];
+  boldItalicFamily: string[];
+}
+
+export interface Padding {
+  x: number;
+  y: number;
 }
 
 export interface Window {
+  // of the terminal background, from 0 to 1
+  opacity: number;
+  decorations: boolean;
+  columns: number | null;
+  rows: number | null;
+  padding: Padding;
+  startupMode: 'normal' | 'maximized' | 'fullscreen';
+  alwaysOnTop: boolean;
   forceTabBar: boolean;
 }
 
@@ -11,8 +98,8 @@ export interface Shell {
   program: string;
   args: string[];
   env: { [key: string]: string };
+  envFile: string | null;
   bell: boolean;
-  fonts: string;
   changeDirectoryOscCode: number;
   changeWindowTitleOscCode: number;
 }
@@ -25,6 +112,8 @@ export interface UserConfiguration {
   window: Window;
   shell: Shell;
   keymaps: CommandKeyMap[];
+  colors?: Colors;
+  font?: Font;
   loaded: boolean;
 }
 
@@ -36,6 +125,8 @@ export interface ShellSession extends IDisposable {
   pid: number;
   rawCwd: string;
   title: string;
+  // the profile's font, when it differs from the font section
+  font: Font | null;
   resize: (cols: number, rows: number) => void;
   write: (data: string) => void;
   kill: () => void;
@@ -45,6 +136,13 @@ export interface ShellSession extends IDisposable {
   onShellExit: (callback: (exitStatus: SessionExitStatus) => void) => () => void;
 }
 
+export interface CreatedSession {
+  pid: number;
+  // of the profile the session was launched with
+  title: string | null;
+  font: Font | null;
+}
+
 export interface CreateSessionInputs {
   args?: string[];
   cols?: number;
@@ -52,6 +150,7 @@ export interface CreateSessionInputs {
   currentWorkingDirectory?: string;
   env?: { [key: string]: string };
   referringSessionId?: number;
+  profile?: string;
 }
 
 export interface SessionExitStatus {
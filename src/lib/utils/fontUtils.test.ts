@@ -0,0 +1,60 @@
+import { expect, test } from 'vitest';
+
+import { toFontFaceRules, toFontFamily, toTerminalFontOptions } from './fontUtils';
+
+test('toFontFamily quotes family names but not generic families', () => {
+  expect(toFontFamily(['JetBrains Mono', ' Consolas ', '', 'Monospace'])).toBe(
+    '"JetBrains Mono", "Consolas", Monospace'
+  );
+});
+
+test('toTerminalFontOptions maps font settings to xterm options', () => {
+  const font = {
+    family: ['Fira Code', 'monospace'],
+    size: 13,
+    lineHeight: 1.2,
+    weight: '300',
+    boldWeight: '600',
+    ligatures: true,
+    boldFamily: [],
+    italicFamily: [],
+    boldItalicFamily: []
+  };
+
+  expect(toTerminalFontOptions(font)).toStrictEqual({
+    fontFamily: '"Fira Code", monospace',
+    fontSize: 13,
+    lineHeight: 1.2,
+    fontWeight: '300',
+    fontWeightBold: '600'
+  });
+  expect(toTerminalFontOptions(undefined)).toStrictEqual({});
+});
+
+test('style families are declared as faces of the styled family', () => {
+  const font = {
+    family: ['Fira Code', 'monospace'],
+    size: 13,
+    lineHeight: 1,
+    weight: 'normal',
+    boldWeight: 'bold',
+    ligatures: false,
+    boldFamily: [],
+    italicFamily: ['Victor Mono Italic'],
+    boldItalicFamily: []
+  };
+
+  const rules = toFontFaceRules(font, 'terminal-1').split('\n');
+
+  expect(rules).toHaveLength(4);
+  expect(rules[1]).toBe(
+    '@font-face { font-family: "terminal-1"; src: local("Fira Code Bold"), local("monospace Bold"); font-weight: bold; font-style: normal; }'
+  );
+  expect(rules[2]).toBe(
+    '@font-face { font-family: "terminal-1"; src: local("Victor Mono Italic"); font-weight: normal; font-style: italic; }'
+  );
+  expect(toTerminalFontOptions(font, 'terminal-1').fontFamily).toBe(
+    '"terminal-1", "Fira Code", monospace'
+  );
+  expect(toFontFaceRules({ ...font, italicFamily: [] }, 'terminal-1')).toBe('');
+});
@@ -0,0 +1,75 @@
+import type { FontWeight, ITerminalOptions } from '@xterm/xterm';
+import type { Font } from '$lib/types';
+
+const GENERIC_FAMILIES = ['monospace', 'ui-monospace', 'serif', 'sans-serif', 'system-ui'];
+
+const quote = (name: string) => `"${name.replace(/"/g, '\\"')}"`;
+
+// a CSS font-family list, quoting names that aren't generic families
+export const toFontFamily = (families: string[]) =>
+  families
+    .map((family) => family.trim())
+    .filter((family) => family.length > 0)
+    .map((family) => (GENERIC_FAMILIES.includes(family.toLowerCase()) ? family : quote(family)))
+    .join(', ');
+
+const hasStyleFamilies = (font: Font) =>
+  [font.boldFamily, font.italicFamily, font.boldItalicFamily].some(
+    (families) => (families ?? []).some((family) => family.trim().length > 0)
+  );
+
+// xterm.js takes a single font family, so bold and italic text get their own fonts through the
+// faces of a family declared for the terminal. A face that can't be found falls back to the
+// regular families
+export const toFontFaceRules = (font: Font, styledFamily: string) => {
+  if (!hasStyleFamilies(font)) {
+    return '';
+  }
+  const names = (families: string[] | undefined) =>
+    (families ?? []).map((family) => family.trim()).filter((family) => family.length > 0);
+  // faces of the regular families are usually named like "Fira Code Bold". Generic families
+  // match no face, but keep every style declared so a missing one falls back rather than
+  // taking the nearest face of the styled family
+  const faceNames = (families: string[] | undefined, suffixes: string[]) =>
+    names(families).length > 0
+      ? names(families)
+      : names(font.family).flatMap((family) => suffixes.map((suffix) => family + suffix));
+  const faces = [
+    { names: faceNames([], ['', ' Regular']), weight: font.weight, style: 'normal' },
+    { names: faceNames(font.boldFamily, [' Bold']), weight: font.boldWeight, style: 'normal' },
+    { names: faceNames(font.italicFamily, [' Italic']), weight: font.weight, style: 'italic' },
+    {
+      names: faceNames(font.boldItalicFamily, [' Bold Italic']),
+      weight: font.boldWeight,
+      style: 'italic'
+    }
+  ];
+  return faces
+    .filter((face) => face.names.length > 0)
+    .map(
+      (face) =>
+        `@font-face { font-family: ${quote(styledFamily)}; ` +
+        `src: ${face.names.map((name) => `local(${quote(name)})`).join(', ')}; ` +
+        `font-weight: ${face.weight}; font-style: ${face.style}; }`
+    )
+    .join('\n');
+};
+
+// styledFamily is the family toFontFaceRules declared, used when styles have their own fonts
+export const toTerminalFontOptions = (
+  font: Font | undefined,
+  styledFamily?: string
+): ITerminalOptions => {
+  if (!font) {
+    return {};
+  }
+  const fontFamily = toFontFamily(font.family);
+  return {
+    fontFamily:
+      styledFamily && hasStyleFamilies(font) ? `${quote(styledFamily)}, ${fontFamily}` : fontFamily,
+    fontSize: font.size,
+    lineHeight: font.lineHeight,
+    fontWeight: font.weight as FontWeight,
+    fontWeightBold: font.boldWeight as FontWeight
+  };
+};
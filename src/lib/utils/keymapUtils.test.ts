@@ -3,7 +3,15 @@ import * as store from 'svelte/store';
 
 import { userConfiguration } from '$lib/store';
 
-import { findKeyCommand, matchKeyboardEvent } from './keymapUtils';
+import { findKeyCommand, matchKeyboardEvent, parseChord } from './keymapUtils';
+
+const commandKeyMap = (commandName: string, ...keyCombos: string[]) => ({
+  commandName,
+  bindings: keyCombos.map((keyCombo) => ({
+    keyCombo,
+    chords: keyCombo.split(' ').map(parseChord)
+  }))
+});
 
 const partialConfig = {
   loaded: true,
@@ -69,10 +77,7 @@ test('matchKeyboardEvent does not match for bad keyCombo', async () => {
 });
 
 test('findKeyCommand gets command from userConfiguration', async () => {
-  const expectedMap = {
-    commandName: 'edit:copy',
-    keyCombo: 'ctrl+shift+c'
-  };
+  const expectedMap = commandKeyMap('edit:copy', 'ctrl+shift+c');
   const userConfig = {
     ...partialConfig,
     keymaps: [expectedMap]
@@ -90,20 +95,14 @@ test('findKeyCommand gets command from userConfiguration', async () => {
   };
 
   const result = findKeyCommand(event as KeyboardEvent);
-  expect(result).toStrictEqual(expectedMap);
+  expect(result).toStrictEqual({ command: expectedMap, binding: expectedMap.bindings[0] });
   expect(getSpy).toHaveBeenCalledOnce();
 });
 
 test('findKeyCommand gets command from fallback map', () => {
-  const expectedMap = {
-    commandName: 'edit:copy',
-    keyCombo: 'ctrl+shift+c'
-  };
+  const expectedMap = commandKeyMap('edit:copy', 'ctrl+shift+c');
 
-  const configMap = {
-    commandName: 'edit:copy',
-    keyCombo: 'ctrl+shift+c'
-  };
+  const configMap = commandKeyMap('edit:paste', 'ctrl+shift+v');
   const userConfig = {
     ...partialConfig,
     keymaps: [configMap]
@@ -120,5 +119,55 @@ test('findKeyCommand gets command from fallback map', () => {
   };
 
   const result = findKeyCommand(event as KeyboardEvent);
-  expect(result).toStrictEqual(expectedMap);
+  expect(result).toStrictEqual({ command: expectedMap, binding: expectedMap.bindings[0] });
+});
+
+test('findKeyCommand matches every binding and chord sequences', () => {
+  const expectedMap = commandKeyMap('window:new_tab', 'ctrl+shift+t', 'ctrl+a c');
+  const userConfig = {
+    ...partialConfig,
+    keymaps: [expectedMap]
+  };
+
+  userConfiguration.set(userConfig);
+
+  const keyDown = (key: string, ctrlKey = false) =>
+    ({ key, ctrlKey, altKey: false, shiftKey: false, metaKey: false }) as KeyboardEvent;
+
+  expect(findKeyCommand(keyDown('a', true))).toBe('pending');
+  expect(findKeyCommand(keyDown('Control'))).toBe('pending');
+  expect(findKeyCommand(keyDown('c'))).toStrictEqual({
+    command: expectedMap,
+    binding: expectedMap.bindings[1]
+  });
+  expect(findKeyCommand(keyDown('c'))).toBeUndefined();
+
+  expect(findKeyCommand(keyDown('a', true))).toBe('pending');
+  expect(findKeyCommand(keyDown('x'))).toBeUndefined();
+  expect(findKeyCommand(keyDown('c'))).toBeUndefined();
+});
+
+test('findKeyCommand returns the args of the binding that matched', () => {
+  const sendText = {
+    commandName: 'terminal:send_text',
+    bindings: [
+      { keyCombo: 'alt+ArrowLeft', chords: [parseChord('alt+ArrowLeft')], args: { text: '\x1bb' } },
+      {
+        keyCombo: 'alt+ArrowRight',
+        chords: [parseChord('alt+ArrowRight')],
+        args: { text: '\x1bf' }
+      }
+    ]
+  };
+  userConfiguration.set({ ...partialConfig, keymaps: [sendText] });
+
+  const result = findKeyCommand({
+    key: 'ArrowRight',
+    ctrlKey: false,
+    altKey: true,
+    shiftKey: false,
+    metaKey: false
+  } as KeyboardEvent);
+
+  expect(result).toStrictEqual({ command: sendText, binding: sendText.bindings[1] });
 });
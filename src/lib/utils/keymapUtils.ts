@@ -1,7 +1,7 @@
 import { readText, writeText } from '@tauri-apps/plugin-clipboard-manager';
 import { get } from 'svelte/store';
 import type { Terminal } from '@xterm/xterm';
-import type { CommandKeyMap, ShellSession } from '$lib/types';
+import type { Chord, CommandKeyMap, KeyBinding, ShellSession } from '$lib/types';
 import { userConfiguration } from '$lib/store';
 
 const HexMap: { [key: string]: string } = {
@@ -9,73 +9,116 @@ const HexMap: { [key: string]: string } = {
   'edit:select_all': '\x01'
 };
 
+const MODIFIER_KEYS = ['Control', 'Shift', 'Alt', 'Meta'];
+
+export const parseChord = (keyCombo: string): Chord => {
+  const tokens = keyCombo.split('+');
+  const key = tokens.splice(tokens.length - 1, 1)[0];
+  return {
+    ctrl: tokens.includes('ctrl'),
+    alt: tokens.includes('alt'),
+    shift: tokens.includes('shift'),
+    meta: tokens.includes('meta'),
+    key
+  };
+};
+
+const fallbackCommand = (commandName: string, keyCombo: string): CommandKeyMap => ({
+  commandName,
+  bindings: [{ keyCombo, chords: [parseChord(keyCombo)] }]
+});
+
 const fallbackMap: CommandKeyMap[] = [
-  {
-    commandName: 'edit:copy',
-    keyCombo: 'ctrl+shift+c'
-  },
-  {
-    commandName: 'edit:paste',
-    keyCombo: 'ctrl+shift+v'
-  },
-  {
-    commandName: 'edit:select_all',
-    keyCombo: 'ctrl+shift+a'
-  },
-  {
-    commandName: 'edit:interrupt',
-    keyCombo: 'ctrl+c'
-  },
-  {
-    commandName: 'window:new_tab',
-    keyCombo: 'ctrl+shift+t'
-  },
-  {
-    commandName: 'window:next_tab',
-    keyCombo: 'ctrl+shift+ArrowRight'
-  },
-  {
-    commandName: 'window:prev_tab',
-    keyCombo: 'ctrl+shift+ArrowLeft'
-  },
-  {
-    commandName: 'window:split_right',
-    keyCombo: 'ctrl+shift+d'
-  },
-  {
-    commandName: 'window:split_down',
-    keyCombo: 'ctrl+shift+e'
-  }
+  fallbackCommand('edit:copy', 'ctrl+shift+c'),
+  fallbackCommand('edit:paste', 'ctrl+shift+v'),
+  fallbackCommand('edit:select_all', 'ctrl+shift+a'),
+  fallbackCommand('edit:interrupt', 'ctrl+c'),
+  fallbackCommand('window:new_tab', 'ctrl+shift+t'),
+  fallbackCommand('window:next_tab', 'ctrl+shift+ArrowRight'),
+  fallbackCommand('window:prev_tab', 'ctrl+shift+ArrowLeft'),
+  fallbackCommand('window:split_right', 'ctrl+shift+d'),
+  fallbackCommand('window:split_down', 'ctrl+shift+e')
 ];
 
-export const matchKeyboardEvent = (keyCombo: string, event: KeyboardEvent) => {
-  const tokens = keyCombo.split('+');
-  const key = tokens.splice(tokens.length - 1, 1)[0].toLowerCase();
-  const ctrl = tokens.includes('ctrl');
-  const alt = tokens.includes('alt');
-  const shift = tokens.includes('shift');
-  const meta = tokens.includes('meta');
+export const matchChord = (chord: Chord, event: KeyboardEvent) => {
+  // the backend normalizes these keys to names since ' ' and '+' can't appear in a combo
+  let eventKey = event.key.toLowerCase();
+  if (eventKey === ' ') {
+    eventKey = 'space';
+  } else if (eventKey === '+') {
+    eventKey = 'plus';
+  }
 
   return (
-    event.key.toLowerCase() === key &&
-    event.ctrlKey === ctrl &&
-    event.altKey === alt &&
-    event.shiftKey === shift &&
-    event.metaKey === meta
+    eventKey === chord.key.toLowerCase() &&
+    event.ctrlKey === chord.ctrl &&
+    event.altKey === chord.alt &&
+    event.shiftKey === chord.shift &&
+    event.metaKey === chord.meta
   );
 };
 
-export const findKeyCommand = (event: KeyboardEvent): CommandKeyMap | undefined => {
-  // prioritize user configuration mappings
-  const userKeyMaps = get(userConfiguration).keymaps;
-  let mapping = userKeyMaps.find((keyMap: CommandKeyMap) =>
-    matchKeyboardEvent(keyMap.keyCombo, event)
+export const matchKeyboardEvent = (keyCombo: string, event: KeyboardEvent) =>
+  matchChord(parseChord(keyCombo), event);
+
+export interface BoundCommand {
+  command: CommandKeyMap;
+  binding: KeyBinding;
+}
+
+// bindings whose first chords have been typed, while in the middle of a sequence like 'ctrl+a c'
+let pendingBindings: BoundCommand[] = [];
+let typedChords = 0;
+
+export const resetKeySequence = () => {
+  pendingBindings = [];
+  typedChords = 0;
+};
+
+const boundCommands = (keyMaps: CommandKeyMap[]): BoundCommand[] =>
+  keyMaps.flatMap((command) => command.bindings.map((binding) => ({ command, binding })));
+
+const matchNextChord = (candidates: BoundCommand[], event: KeyboardEvent) =>
+  candidates.filter(
+    ({ binding }) =>
+      binding.chords.length > typedChords && matchChord(binding.chords[typedChords], event)
   );
-  // use fallback map if command not found
-  if (mapping === undefined) {
-    mapping = fallbackMap.find((keyMap) => matchKeyboardEvent(keyMap.keyCombo, event));
+
+// Returns the command and the binding that matched once the last chord of one of its bindings
+// is typed, or 'pending' while a sequence has been started but not finished
+export const findKeyCommand = (event: KeyboardEvent): BoundCommand | 'pending' | undefined => {
+  if (MODIFIER_KEYS.includes(event.key)) {
+    return pendingBindings.length > 0 ? 'pending' : undefined;
+  }
+
+  let matches: BoundCommand[];
+  if (pendingBindings.length > 0) {
+    matches = matchNextChord(pendingBindings, event);
+    if (matches.length === 0) {
+      // a key that doesn't continue the sequence starts a new one
+      resetKeySequence();
+      return findKeyCommand(event);
+    }
+  } else {
+    // prioritize user configuration mappings, use the fallback map if no command is found
+    const userKeyMaps = get(userConfiguration).keymaps;
+    matches = matchNextChord(boundCommands(userKeyMaps), event);
+    if (matches.length === 0) {
+      matches = matchNextChord(boundCommands(fallbackMap), event);
+    }
+    if (matches.length === 0) {
+      return undefined;
+    }
   }
-  return mapping;
+
+  const complete = matches.find(({ binding }) => binding.chords.length === typedChords + 1);
+  if (complete) {
+    resetKeySequence();
+    return complete;
+  }
+  pendingBindings = matches;
+  typedChords += 1;
+  return 'pending';
 };
 
 export const getKeyboardEventHandler = ({
@@ -87,10 +130,16 @@ export const getKeyboardEventHandler = ({
   terminal: Terminal;
   dispatch: (screenCommand: string) => void;
 }) => {
-  const handleKeyMapEvent = (command: CommandKeyMap): boolean => {
+  const handleKeyMapEvent = ({ command, binding }: BoundCommand): boolean => {
     // console.log('map found for ' + command.commandName);
 
-    if (command.commandName.includes('window')) {
+    if (command.commandName === 'terminal:send_text') {
+      const text = binding.args?.text;
+      if (text) {
+        session.write(text);
+      }
+      return false;
+    } else if (command.commandName.includes('window')) {
       dispatch(command.commandName);
       return false;
     } else if (command.commandName in HexMap) {
@@ -119,11 +168,15 @@ export const getKeyboardEventHandler = ({
   return (event: KeyboardEvent) => {
     if (event.type === 'keydown') {
       // console.log(event);
-      const command = findKeyCommand(event);
-      if (command) {
-        // console.log(command);
+      const bound = findKeyCommand(event);
+      if (bound === 'pending') {
+        event.preventDefault();
+        return false;
+      }
+      if (bound) {
+        // console.log(bound.command);
         event.preventDefault();
-        return handleKeyMapEvent(command);
+        return handleKeyMapEvent(bound);
       }
     }
     return true;
@@ -0,0 +1,73 @@
+import { expect, test } from 'vitest';
+
+import { toPaneBackground, toTerminalTheme, withOpacity } from './themeUtils';
+
+test('toTerminalTheme maps colors to xterm theme keys', () => {
+  const palette = {
+    black: '#000000',
+    red: '#cc0000',
+    green: '#4e9a06',
+    yellow: '#c4a000',
+    blue: '#3465a4',
+    magenta: '#75507b',
+    cyan: '#06989a',
+    white: '#d3d7cf',
+    brightBlack: '#555753',
+    brightRed: '#ef2929',
+    brightGreen: '#8ae234',
+    brightYellow: '#fce94f',
+    brightBlue: '#729fcf',
+    brightMagenta: '#ad7fa8',
+    brightCyan: '#34e2e2',
+    brightWhite: '#eeeeec'
+  };
+
+  const theme = toTerminalTheme({
+    foreground: '#ffffff',
+    background: '#1e1e2e',
+    cursor: '#f5e0dc',
+    selection: '#45475a',
+    palette
+  });
+
+  expect(theme).toStrictEqual({
+    foreground: '#ffffff',
+    background: '#1e1e2e',
+    cursor: '#f5e0dc',
+    selectionBackground: '#45475a',
+    ...palette
+  });
+});
+
+test('toTerminalTheme keeps the default background before the config loads', () => {
+  expect(toTerminalTheme(undefined)).toStrictEqual({ background: '#020617' });
+});
+
+test('withOpacity adds an alpha channel to opaque colors', () => {
+  expect(withOpacity('#1e1e2e', 0.5)).toBe('#1e1e2e80');
+  expect(withOpacity('#1e1e2e', 1)).toBe('#1e1e2e');
+  expect(withOpacity('#1e1e2e40', 0.5)).toBe('#1e1e2e40');
+  expect(toTerminalTheme(undefined, 0)).toStrictEqual({ background: '#02061700' });
+});
+
+const alphaOf = (color: string) => {
+  if (color === 'transparent') {
+    return 0;
+  }
+  return color.length === 9 ? parseInt(color.slice(7), 16) / 255 : 1;
+};
+
+// the alpha of layers drawn over each other, as the compositor blends them
+const stackedAlpha = (...colors: string[]) =>
+  1 - colors.reduce((remaining, color) => remaining * (1 - alphaOf(color)), 1);
+
+test('the pane and terminal backgrounds together have the configured opacity', () => {
+  for (const opacity of [0.5, 0.8, 1]) {
+    const alpha = stackedAlpha(
+      toPaneBackground(undefined, opacity),
+      toTerminalTheme(undefined, opacity).background!
+    );
+    expect(alpha).toBeCloseTo(opacity, 2);
+  }
+  expect(toPaneBackground(undefined)).toBe('#020617');
+});
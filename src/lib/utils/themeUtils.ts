@@ -0,0 +1,32 @@
+import type { ITheme } from '@xterm/xterm';
+import type { Colors } from '$lib/types';
+
+// the background the terminal had before colors could be configured
+const DEFAULT_BACKGROUND = '#020617';
+
+// #rrggbb with the opacity as an alpha channel, colors that already have one are kept
+export const withOpacity = (color: string, opacity: number) => {
+  if (opacity >= 1 || !/^#[0-9a-f]{6}$/i.test(color)) {
+    return color;
+  }
+  const alpha = Math.round(Math.max(opacity, 0) * 255);
+  return `${color}${alpha.toString(16).padStart(2, '0')}`;
+};
+
+export const toTerminalTheme = (colors: Colors | undefined, opacity = 1): ITheme => {
+  if (!colors) {
+    return { background: withOpacity(DEFAULT_BACKGROUND, opacity) };
+  }
+  return {
+    foreground: colors.foreground,
+    background: withOpacity(colors.background, opacity),
+    cursor: colors.cursor,
+    selectionBackground: colors.selection,
+    ...colors.palette
+  };
+};
+
+// The pane around the terminal, which fills its padding. When the window is translucent only
+// the terminal draws the background, otherwise the alpha of both would stack
+export const toPaneBackground = (colors: Colors | undefined, opacity = 1) =>
+  opacity < 1 ? 'transparent' : toTerminalTheme(colors).background;